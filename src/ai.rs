@@ -0,0 +1,537 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::bot::Bot;
+use crate::engine::*;
+use crate::master::FRAMES_PER_SECOND;
+use crate::pathfinder::*;
+use crate::pieces::PieceSet;
+use crate::types::*;
+
+//Frames the AI keeps trying to get the tetromino to its place before it drops it where it is.
+const STUCK_FRAMES: u32 = 4;
+
+//How well the AI plays. Harder AIs think for less time before moving a tetromino and place more
+//pieces every second.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    //As fast as the game lets it play.
+    Max,
+}
+
+pub const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Easy,
+    Difficulty::Medium,
+    Difficulty::Hard,
+    Difficulty::Max,
+];
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Max => "max",
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Medium,
+            Difficulty::Medium => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Max,
+            Difficulty::Max => Difficulty::Easy,
+        }
+    }
+
+    //Frames the AI looks at a new tetromino before it starts moving it.
+    pub fn think_frames(&self) -> u32 {
+        match self {
+            Difficulty::Easy => FRAMES_PER_SECOND / 2,
+            Difficulty::Medium => FRAMES_PER_SECOND / 4,
+            Difficulty::Hard => FRAMES_PER_SECOND / 12,
+            Difficulty::Max => 0,
+        }
+    }
+
+    //Fewest frames between two hard drops, which caps the pieces placed every second.
+    pub fn frames_per_piece(&self) -> u32 {
+        match self {
+            Difficulty::Easy => FRAMES_PER_SECOND,
+            Difficulty::Medium => FRAMES_PER_SECOND / 2,
+            Difficulty::Hard => FRAMES_PER_SECOND / 4,
+            Difficulty::Max => 0,
+        }
+    }
+}
+
+//How much each feature of the stack counts when the AI compares placements. Features with
+//positive weights are good for the stack, those with negative weights bad.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Weights {
+    //Lines the placement cleared.
+    pub lines: f32,
+    //Heights of all columns added up.
+    pub height: f32,
+    //Empty cells under a filled one.
+    pub holes: f32,
+    //Height differences between neighbouring columns added up.
+    pub bumpiness: f32,
+    //Columns lower than both their neighbours, counting 1 + 2 + ... for every row of depth.
+    pub wells: f32,
+    //Spots a T piece can be spun into to clear lines.
+    pub t_slots: f32,
+    //Filled cells in the hidden rows, where the stack is about to top out.
+    pub hidden: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights {
+            lines: 0.76,
+            height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.05,
+            t_slots: 0.3,
+            hidden: -5.0,
+        }
+    }
+}
+
+//The stack as rows of bits, which is a lot quicker to try placements on than the grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    pub width: u32,
+    //Rows from the top down, with a bit set for every filled column.
+    pub rows: Vec<u32>,
+}
+
+impl Board {
+    pub fn from_grid(grid: &Grid) -> Board {
+        Board {
+            width: grid.width,
+            rows: (0..grid.height)
+                .map(|y| {
+                    (0..grid.width)
+                        .filter(|x| grid.check_occupied(*x, y))
+                        .fold(0, |row, x| row | 1 << x)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    //Whether a cell is filled. Everything outside the board counts as filled, like the walls.
+    pub fn is_filled(&self, x: i32, y: i32) -> bool {
+        x < 0
+            || y < 0
+            || x >= self.width as i32
+            || y >= self.height() as i32
+            || self.rows[y as usize] & 1 << x != 0
+    }
+
+    pub fn collides(&self, pieces: &PieceSet, tetromino: &Tetromino) -> bool {
+        cells(pieces, tetromino)
+            .iter()
+            .any(|(x, y)| self.is_filled(*x, *y))
+    }
+
+    //Locks a tetromino into the board and clears the rows it filled. Returns the number of rows
+    //cleared.
+    pub fn place(&mut self, pieces: &PieceSet, tetromino: &Tetromino) -> u32 {
+        for (x, y) in cells(pieces, tetromino) {
+            self.rows[y as usize] |= 1 << x;
+        }
+        self.clear_full_rows()
+    }
+
+    //Removes the filled rows, moving the ones above down. Returns the number of rows removed.
+    pub fn clear_full_rows(&mut self) -> u32 {
+        let full = (1 << self.width) - 1;
+        let height = self.rows.len();
+        self.rows.retain(|row| *row != full);
+        let cleared = height - self.rows.len();
+        let mut rows = vec![0; cleared];
+        rows.append(&mut self.rows);
+        self.rows = rows;
+        cleared as u32
+    }
+
+    //Height of every column, counted from the floor.
+    pub fn column_heights(&self) -> Vec<u32> {
+        (0..self.width)
+            .map(|x| {
+                self.rows
+                    .iter()
+                    .position(|row| row & 1 << x != 0)
+                    .map_or(0, |y| self.height() - y as u32)
+            })
+            .collect()
+    }
+
+    //Empty cells under a filled one.
+    pub fn holes(&self) -> u32 {
+        let height = self.height();
+        self.column_heights()
+            .iter()
+            .enumerate()
+            .map(|(x, column)| {
+                (height - column..height)
+                    .filter(|y| !self.is_filled(x as i32, *y as i32))
+                    .count() as u32
+            })
+            .sum()
+    }
+}
+
+//Where the blocks of a tetromino are on the board.
+pub fn cells(pieces: &PieceSet, tetromino: &Tetromino) -> Vec<(i32, i32)> {
+    tetromino
+        .blocks(pieces)
+        .iter()
+        .map(|block| {
+            (
+                (tetromino.position.x + block.position.x) as i32,
+                (tetromino.position.y + block.position.y) as i32,
+            )
+        })
+        .collect()
+}
+
+//Drops a tetromino as far down as it goes.
+pub fn dropped(board: &Board, pieces: &PieceSet, tetromino: &Tetromino) -> Tetromino {
+    let mut tetromino = *tetromino;
+    while let Some(lower) = moved_tetromino(&tetromino, &MoveDirection::Down, pieces, |moved| {
+        board.collides(pieces, moved)
+    }) {
+        tetromino = lower;
+    }
+    tetromino
+}
+
+//Scores the board left by a placement that cleared `lines` lines. Higher is better.
+pub fn evaluate(board: &Board, lines: u32, hidden_rows: u32, weights: &Weights) -> f32 {
+    let heights = board.column_heights();
+    let height = board.height();
+
+    let bumpiness = heights
+        .windows(2)
+        .map(|pair| pair[0].max(pair[1]) - pair[0].min(pair[1]))
+        .sum::<u32>();
+    //The walls count as neighbours as high as the board.
+    let wells = (0..heights.len())
+        .map(|x| {
+            let left = if x == 0 { height } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(height);
+            let depth = left.min(right).saturating_sub(heights[x]);
+            depth * (depth + 1) / 2
+        })
+        .sum::<u32>();
+    let hidden = board.rows[..hidden_rows as usize]
+        .iter()
+        .map(|row| row.count_ones())
+        .sum::<u32>();
+
+    weights.lines * lines as f32
+        + weights.height * heights.iter().sum::<u32>() as f32
+        + weights.holes * board.holes() as f32
+        + weights.bumpiness * bumpiness as f32
+        + weights.wells * wells as f32
+        + weights.t_slots * t_slots(board) as f32
+        + weights.hidden * hidden as f32
+}
+
+//Counts the spots a T piece pointing down can be spun into: three empty cells in a row with one
+//under the middle, the cells on either side of that filled and an overhang on one side above,
+//so the T can't be dropped straight in.
+fn t_slots(board: &Board) -> u32 {
+    let mut slots = 0;
+    for y in 1..board.height() as i32 - 1 {
+        for x in 1..board.width as i32 - 1 {
+            let open = [(x - 1, y), (x, y), (x + 1, y), (x, y + 1), (x, y - 1)]
+                .iter()
+                .all(|(x, y)| !board.is_filled(*x, *y));
+            if open
+                && board.is_filled(x - 1, y + 1)
+                && board.is_filled(x + 1, y + 1)
+                && (board.is_filled(x - 1, y - 1) || board.is_filled(x + 1, y - 1))
+            {
+                slots += 1;
+            }
+        }
+    }
+    slots
+}
+
+//A place the AI can put the tetromino in play.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Placement {
+    //Whether the tetromino in play is put on hold first, and the other one placed instead.
+    pub hold: bool,
+    //The tetromino where it comes to rest.
+    pub tetromino: Tetromino,
+    pub score: f32,
+}
+
+//The best placement for the tetromino in play, or with `hold` also for the one it can be swapped
+//for.
+pub fn best_placement(engine: &Engine, weights: &Weights, hold: bool) -> Option<Placement> {
+    let board = Board::from_grid(&engine.grid);
+    let mut candidates = vec![(false, engine.tetromino?)];
+    if hold && engine.can_hold() {
+        if let Some(other) = engine.tetromino_hold.or(engine.tetromino_next) {
+            candidates.push((true, other));
+        }
+    }
+
+    let mut best: Option<Placement> = None;
+    for (hold, start) in candidates {
+        for path in paths(&board, &engine.pieces, &start) {
+            let tetromino = path.tetromino;
+            let mut after = board.clone();
+            let lines = after.place(&engine.pieces, &tetromino);
+            let score = evaluate(&after, lines, engine.options.hidden_rows, weights);
+            let better = match best {
+                Some(best) => score > best.score,
+                None => true,
+            };
+            if better {
+                best = Some(Placement {
+                    hold,
+                    tetromino,
+                    score,
+                });
+            }
+        }
+    }
+    best
+}
+
+//Takes the tetromino in play to a placement with the inputs a player has, one every frame: it
+//holds if the placement needs it, then follows the shortest path there and hard drops it. DAS
+//and soft drops are held down until the tetromino gets where they take it, like a player would.
+pub struct Pilot {
+    target: Option<Placement>,
+    //Presses left on the path, with where each of them takes the tetromino. None until the path
+    //is worked out, once the tetromino to place is in play.
+    path: Option<VecDeque<(Press, Tetromino)>>,
+    //Where the tetromino was when it was last moved, and for how many frames moving it hasn't
+    //worked.
+    moved_from: Option<Tetromino>,
+    stuck: u32,
+    //The press held down on the last frame, and for how many frames in a row it hasn't moved the
+    //tetromino.
+    holding: Option<Press>,
+    held_still: u32,
+}
+
+impl Pilot {
+    pub fn new() -> Pilot {
+        Pilot {
+            target: None,
+            path: None,
+            moved_from: None,
+            stuck: 0,
+            holding: None,
+            held_still: 0,
+        }
+    }
+
+    //Heads for a new placement. Without one the tetromino is dropped where it is.
+    pub fn set_target(&mut self, target: Option<Placement>) {
+        self.target = target;
+        self.path = None;
+        self.moved_from = None;
+        self.stuck = 0;
+        self.holding = None;
+        self.held_still = 0;
+    }
+
+    //The inputs that take the tetromino in play a step closer to its placement. Once it is there,
+    //or can't be moved any closer, that is a hard drop.
+    pub fn next_input(&mut self, engine: &Engine) -> FrameInput {
+        let mut input = FrameInput::default();
+        let tetromino = match engine.tetromino {
+            Some(tetromino) => tetromino,
+            None => return input,
+        };
+        if self.moved_from.take() == Some(tetromino) {
+            match self.holding {
+                //Held presses leave the tetromino where it is while DAS charges.
+                Some(_) => self.held_still += 1,
+                None => self.stuck += 1,
+            }
+        } else {
+            self.held_still = 0;
+        }
+        if self.held_still > engine.das() + engine.arr() + STUCK_FRAMES {
+            self.stuck = STUCK_FRAMES;
+        }
+
+        let target = match self.target.as_mut() {
+            Some(target) if self.stuck < STUCK_FRAMES => target,
+            _ => {
+                self.holding = None;
+                input.press(INPUT_HARD_DROP);
+                return input;
+            }
+        };
+        if target.hold {
+            target.hold = false;
+            input.press(INPUT_HOLD);
+            return input;
+        }
+        let goal = target.tetromino;
+        let path = self
+            .path
+            .get_or_insert_with(|| plan(engine, &tetromino, &goal));
+
+        //Gravity may have pulled the tetromino lower than the path expects, which is fine as long
+        //as it is in the right column and turned the right way.
+        while let Some((_, to)) = path.front() {
+            let reached = to.rotation == tetromino.rotation
+                && to.position.x == tetromino.position.x
+                && to.position.y <= tetromino.position.y;
+            if !reached {
+                break;
+            }
+            path.pop_front();
+        }
+        let press = match path.front() {
+            Some((press, _)) => *press,
+            None => {
+                self.holding = None;
+                input.press(INPUT_HARD_DROP);
+                return input;
+            }
+        };
+        self.moved_from = Some(tetromino);
+        if let Press::DasLeft | Press::DasRight | Press::SoftDrop = press {
+            input.held = press.input();
+            if self.holding != Some(press) {
+                input.press(press.input());
+            }
+            self.holding = Some(press);
+        } else {
+            input.press(press.input());
+            self.holding = None;
+        }
+        input
+    }
+}
+
+//The presses that take a tetromino to where `goal` is, and where each of them takes it. There
+//are none if it can't get there.
+fn plan(engine: &Engine, tetromino: &Tetromino, goal: &Tetromino) -> VecDeque<(Press, Tetromino)> {
+    let board = Board::from_grid(&engine.grid);
+    let path = match path_to(&board, &engine.pieces, tetromino, goal) {
+        Some(path) => path,
+        None => return VecDeque::new(),
+    };
+    let mut steps = VecDeque::new();
+    let mut at = *tetromino;
+    for press in path.presses {
+        match press.apply(&board, &engine.pieces, &at) {
+            Some(to) => at = to,
+            None => break,
+        }
+        steps.push_back((press, at));
+    }
+    steps
+}
+
+impl Default for Pilot {
+    fn default() -> Pilot {
+        Pilot::new()
+    }
+}
+
+//Plays like a player would: it picks the best placement for each tetromino as it comes into
+//play and steers it there.
+pub struct AiBot {
+    difficulty: Difficulty,
+    weights: Weights,
+    pilot: Pilot,
+    //Tetrominoes locked when the one in play came in, to tell when the next one does.
+    piece: Option<u32>,
+    spawned_at: u32,
+    dropped_at: u32,
+}
+
+impl AiBot {
+    pub fn new(difficulty: Difficulty) -> AiBot {
+        AiBot {
+            difficulty,
+            weights: Weights::default(),
+            pilot: Pilot::new(),
+            piece: None,
+            spawned_at: 0,
+            dropped_at: 0,
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+}
+
+impl Bot for AiBot {
+    fn name(&self) -> String {
+        format!("AI, {}", self.difficulty.name())
+    }
+
+    fn next_input(&mut self, engine: &Engine) -> FrameInput {
+        let input = FrameInput::default();
+        if engine.phase != Phase::Falling || engine.tetromino.is_none() {
+            return input;
+        }
+
+        if self.piece != Some(engine.pieces_locked) {
+            self.piece = Some(engine.pieces_locked);
+            self.spawned_at = engine.frame;
+            self.pilot
+                .set_target(best_placement(engine, &self.weights, true));
+        }
+        if engine.frame < self.spawned_at + self.difficulty.think_frames() {
+            return input;
+        }
+
+        let next = self.pilot.next_input(engine);
+        if next.is_pressed(INPUT_HARD_DROP) {
+            if engine.frame < self.dropped_at + self.difficulty.frames_per_piece() {
+                return input;
+            }
+            self.dropped_at = engine.frame;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn ai_drill_has_no_finesse_faults() {
+        let pieces = Arc::new(PieceSet::standard());
+        let mut engine = Engine::new(GameMode::Drill, GameOptions::default(), pieces, 7);
+        engine.start();
+        let mut bot = AiBot::new(Difficulty::Max);
+        while engine.phase != Phase::GameOver && engine.frame < 100_000 {
+            let input = bot.next_input(&engine);
+            engine.tick(input);
+        }
+        assert_eq!(engine.pieces_locked, crate::finesse::DRILL_PIECES);
+        assert_eq!(engine.finesse.faults, 0);
+    }
+}
@@ -0,0 +1,272 @@
+use ggez::audio::{SoundData, SoundSource, Source};
+use ggez::Context;
+use std::f32::consts::PI;
+
+use crate::config::Audio;
+use crate::engine::*;
+use crate::types::*;
+
+const SAMPLE_RATE: u32 = 22050;
+
+//How much faster the music gets with every level, and how fast it can get.
+const MUSIC_SPEEDUP: f32 = 0.05;
+const MAX_MUSIC_SPEED: f32 = 1.5;
+
+//The opening of Korobeiniki, as (frequency, seconds) pairs.
+const MUSIC_NOTES: [(f32, f32); 19] = [
+    (659.3, 0.4),
+    (493.9, 0.2),
+    (523.3, 0.2),
+    (587.3, 0.4),
+    (523.3, 0.2),
+    (493.9, 0.2),
+    (440.0, 0.4),
+    (440.0, 0.2),
+    (523.3, 0.2),
+    (659.3, 0.4),
+    (587.3, 0.2),
+    (523.3, 0.2),
+    (493.9, 0.6),
+    (523.3, 0.2),
+    (587.3, 0.4),
+    (659.3, 0.4),
+    (523.3, 0.4),
+    (440.0, 0.4),
+    (440.0, 0.8),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sound {
+    Move,
+    Rotate,
+    Lock,
+    HardDrop,
+    Hold,
+    Single,
+    Double,
+    Triple,
+    Rustris,
+    TSpin,
+    LevelUp,
+    GameOver,
+}
+
+const SOUNDS: [Sound; 12] = [
+    Sound::Move,
+    Sound::Rotate,
+    Sound::Lock,
+    Sound::HardDrop,
+    Sound::Hold,
+    Sound::Single,
+    Sound::Double,
+    Sound::Triple,
+    Sound::Rustris,
+    Sound::TSpin,
+    Sound::LevelUp,
+    Sound::GameOver,
+];
+
+impl Sound {
+    //Name of the file in resources/sounds the sound is read from.
+    fn name(&self) -> &'static str {
+        match self {
+            Sound::Move => "move",
+            Sound::Rotate => "rotate",
+            Sound::Lock => "lock",
+            Sound::HardDrop => "hard_drop",
+            Sound::Hold => "hold",
+            Sound::Single => "single",
+            Sound::Double => "double",
+            Sound::Triple => "triple",
+            Sound::Rustris => "rustris",
+            Sound::TSpin => "t_spin",
+            Sound::LevelUp => "level_up",
+            Sound::GameOver => "game_over",
+        }
+    }
+
+    //Notes played instead when there is no file for the sound.
+    fn notes(&self) -> &'static [(f32, f32)] {
+        match self {
+            Sound::Move => &[(440.0, 0.03)],
+            Sound::Rotate => &[(660.0, 0.04)],
+            Sound::Lock => &[(220.0, 0.06)],
+            Sound::HardDrop => &[(330.0, 0.03), (165.0, 0.07)],
+            Sound::Hold => &[(523.3, 0.04), (659.3, 0.04)],
+            Sound::Single => &[(523.3, 0.08), (659.3, 0.1)],
+            Sound::Double => &[(523.3, 0.07), (659.3, 0.07), (784.0, 0.1)],
+            Sound::Triple => &[(523.3, 0.06), (659.3, 0.06), (784.0, 0.06), (1046.5, 0.12)],
+            Sound::Rustris => &[
+                (523.3, 0.06),
+                (659.3, 0.06),
+                (784.0, 0.06),
+                (1046.5, 0.06),
+                (1318.5, 0.2),
+            ],
+            Sound::TSpin => &[(880.0, 0.05), (740.0, 0.05), (880.0, 0.1)],
+            Sound::LevelUp => &[(392.0, 0.08), (523.3, 0.08), (659.3, 0.08), (784.0, 0.16)],
+            Sound::GameOver => &[(392.0, 0.2), (329.6, 0.2), (261.6, 0.2), (196.0, 0.4)],
+        }
+    }
+
+    fn from_event(event: &GameEvent) -> Option<Sound> {
+        match event {
+            GameEvent::Move => Some(Sound::Move),
+            GameEvent::Rotate => Some(Sound::Rotate),
+            GameEvent::Lock => Some(Sound::Lock),
+            GameEvent::HardDrop => Some(Sound::HardDrop),
+            GameEvent::Hold => Some(Sound::Hold),
+            GameEvent::LineClear(LineClears::Single) => Some(Sound::Single),
+            GameEvent::LineClear(LineClears::Double) => Some(Sound::Double),
+            GameEvent::LineClear(LineClears::Tripple) => Some(Sound::Triple),
+            GameEvent::LineClear(LineClears::Rustris)
+            | GameEvent::LineClear(LineClears::More(_)) => Some(Sound::Rustris),
+            GameEvent::LineClear(LineClears::NoClear) => None,
+            GameEvent::TSpin(_) => Some(Sound::TSpin),
+            GameEvent::Attack(_) => None,
+            GameEvent::LevelUp => Some(Sound::LevelUp),
+            GameEvent::GameOver => Some(Sound::GameOver),
+        }
+    }
+}
+
+//Plays the sound effects and music. Sounds are read from resources/sounds/<name>.ogg, and any
+//that are missing are made up from simple tones.
+pub struct Sounds {
+    effects: Vec<(Sound, Source)>,
+    music: Option<Source>,
+    audio: Audio,
+}
+
+impl Sounds {
+    pub fn new(ctx: &mut Context, audio: &Audio) -> Sounds {
+        let effects = SOUNDS
+            .iter()
+            .filter_map(|sound| Some((*sound, load(ctx, sound.name(), sound.notes())?)))
+            .collect();
+        let mut music = load(ctx, "music", &MUSIC_NOTES);
+        if let Some(music) = music.as_mut() {
+            music.set_repeat(true);
+        }
+
+        let mut sounds = Sounds {
+            effects,
+            music,
+            audio: *audio,
+        };
+        sounds.set_volumes(audio);
+        sounds
+    }
+
+    pub fn set_volumes(&mut self, audio: &Audio) {
+        self.audio = *audio;
+        let effects_volume = audio.volume * audio.effects_volume;
+        self.effects
+            .iter_mut()
+            .for_each(|(_, source)| source.set_volume(effects_volume));
+        if let Some(music) = self.music.as_mut() {
+            music.set_volume(audio.volume * audio.music_volume);
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        if let Some((_, source)) = self.effects.iter_mut().find(|(other, _)| *other == sound) {
+            if let Err(e) = source.play_detached() {
+                println!("Couldn't play sound {}: {}", sound.name(), e);
+            }
+        }
+    }
+
+    //Plays the sounds of what happened on a frame, each of them once. The music speeds up when
+    //the game reaches a new stage.
+    pub fn play_events(&mut self, events: &[GameEvent], stage: u32) {
+        let mut played = Vec::new();
+        for sound in events.iter().filter_map(Sound::from_event) {
+            if !played.contains(&sound) {
+                self.play(sound);
+                played.push(sound);
+            }
+        }
+        if played.contains(&Sound::LevelUp) && self.audio.music_speedup {
+            self.start_music(stage);
+        }
+    }
+
+    //Plays the music from the start, at the speed of the given stage of the game.
+    pub fn start_music(&mut self, stage: u32) {
+        let speed = if self.audio.music_speedup {
+            (1.0 + stage as f32 * MUSIC_SPEEDUP).min(MAX_MUSIC_SPEED)
+        } else {
+            1.0
+        };
+        if let Some(music) = self.music.as_mut() {
+            music.set_pitch(speed);
+            if let Err(e) = music.play() {
+                println!("Couldn't play the music: {}", e);
+            }
+        }
+    }
+
+    pub fn stop_music(&mut self) {
+        if let Some(music) = self.music.as_mut() {
+            music.stop();
+        }
+    }
+
+    pub fn pause_music(&self) {
+        if let Some(music) = self.music.as_ref() {
+            music.pause();
+        }
+    }
+
+    pub fn resume_music(&self) {
+        if let Some(music) = self.music.as_ref() {
+            music.resume();
+        }
+    }
+}
+
+//Reads a sound from resources/sounds, or makes it up from the notes if there is no file for it.
+fn load(ctx: &mut Context, name: &str, notes: &[(f32, f32)]) -> Option<Source> {
+    Source::new(ctx, format!("/sounds/{}.ogg", name))
+        .or_else(|_| Source::from_data(ctx, SoundData::from_bytes(&synthesize(notes))))
+        .map_err(|e| println!("Couldn't load sound {}: {}", name, e))
+        .ok()
+}
+
+//Makes a WAV file of the notes played one after the other, each a sine wave that fades out.
+fn synthesize(notes: &[(f32, f32)]) -> Vec<u8> {
+    let samples = notes
+        .iter()
+        .flat_map(|(frequency, seconds)| {
+            let count = (seconds * SAMPLE_RATE as f32) as u32;
+            (0..count).map(move |i| {
+                let time = i as f32 / SAMPLE_RATE as f32;
+                let attack = (time / 0.005).min(1.0);
+                let decay = 1.0 - i as f32 / count as f32;
+                let wave = (2.0 * PI * frequency * time).sin();
+                (wave * attack * decay * 0.5 * i16::MAX as f32) as i16
+            })
+        })
+        .collect::<Vec<i16>>();
+
+    let data_length = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_length as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    //PCM, one channel, 16 bits per sample.
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    samples
+        .iter()
+        .for_each(|sample| wav.extend_from_slice(&sample.to_le_bytes()));
+    wav
+}
@@ -0,0 +1,329 @@
+//Plays games without a window, as fast as it can, and prints how they went as JSON. Games are
+//played by a bot or from a script of inputs, on the same engine as the game itself.
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+use rustris::bot::{self, Bot};
+use rustris::config;
+use rustris::engine::*;
+use rustris::pieces::{self, PieceSet};
+use rustris::replay::*;
+use rustris::tbp::{self, TbpBot};
+
+const USAGE: &str =
+    "Usage: rustris-sim [options] (--bot NAME | --tbp COMMAND | --inputs FILE | --replay FILE)
+
+  --bot NAME         Let a bot play: idle, random or ai-easy, ai-medium, ai-hard and ai-max
+  --tbp COMMAND      Let a bot that speaks the Tetris Bot Protocol play, started with COMMAND
+  --inputs FILE      Play the inputs in FILE, written the same way as in replays
+  --replay FILE      Play a replay again, with its seed, rules and piece set
+  --seed SEED        Seed of the game in hex, as in replays (random if left out)
+  --mode MODE        marathon, master or drill (marathon if left out)
+  --rules FILE       Config file to take the rules and handling from (the defaults if left out)
+  --pieces NAME      Piece set to play with (Standard if left out)
+  --max-frames N     Stop the game after N frames (216000, an hour, if left out)";
+
+//An hour of play.
+const DEFAULT_MAX_FRAMES: u32 = 216_000;
+
+//What plays the game.
+enum Player {
+    Bot(Box<dyn Bot>),
+    Inputs(Vec<FrameInput>),
+}
+
+//The game to play, as read from the command line.
+struct Simulation {
+    seed: u64,
+    mode: GameMode,
+    options: GameOptions,
+    pieces: Arc<PieceSet>,
+    player: Player,
+    max_frames: u32,
+}
+
+//How a game went, printed as JSON.
+#[derive(Serialize)]
+struct SimResult {
+    seed: String,
+    mode: GameMode,
+    piece_set: String,
+    frames: u32,
+    score: u64,
+    lines: u32,
+    single: u32,
+    double: u32,
+    triple: u32,
+    rustris: u32,
+    //Lines cleared five or more at a time, by pieces taller than a tetromino.
+    more_lines: u32,
+    pieces: u32,
+    //Marathon level, or master level.
+    level: u32,
+    //Only in master mode.
+    grade: Option<String>,
+    //Tetrominoes placed with more presses than needed. Master mode doesn't keep count.
+    finesse_faults: u32,
+    //Only in drills.
+    targets_hit: Option<u32>,
+    //block_out, lock_out, top_out or completed when the game ended by itself, otherwise
+    //out_of_inputs or frame_limit.
+    end: String,
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let simulation = match parse_args(&args) {
+        Ok(simulation) => simulation,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let started = Instant::now();
+    match simulation.run() {
+        Ok(result) => {
+            let seconds = started.elapsed().as_secs_f64();
+            eprintln!(
+                "Played {} frames in {:.3}s ({:.0} frames per second)",
+                result.frames,
+                seconds,
+                result.frames as f64 / seconds.max(1e-9)
+            );
+            println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        }
+        Err(e) => {
+            eprintln!("Error occured: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+impl Simulation {
+    fn run(mut self) -> Result<SimResult, String> {
+        let mut engine = Engine::new(self.mode, self.options, self.pieces.clone(), self.seed);
+        engine.start();
+
+        let mut frames = 0;
+        let end = loop {
+            if let Some(end) = engine.end {
+                break String::from(match end {
+                    GameEnd::BlockOut => "block_out",
+                    GameEnd::LockOut => "lock_out",
+                    GameEnd::Completed => "completed",
+                    GameEnd::TopOut => "top_out",
+                });
+            }
+            if frames >= self.max_frames {
+                break String::from("frame_limit");
+            }
+            let input = match &mut self.player {
+                Player::Bot(bot) => bot.next_input(&engine),
+                Player::Inputs(inputs) => match inputs.get(frames as usize) {
+                    Some(input) => *input,
+                    None => break String::from("out_of_inputs"),
+                },
+            };
+            engine.tick(input);
+            frames += 1;
+        };
+
+        Ok(SimResult {
+            seed: format!("{:016x}", self.seed),
+            mode: self.mode,
+            piece_set: self.pieces.name.clone(),
+            frames,
+            score: match self.mode {
+                GameMode::Marathon | GameMode::Drill => engine.score,
+                GameMode::Master => engine.master.score,
+            },
+            lines: engine.lines(),
+            single: engine.single,
+            double: engine.double,
+            triple: engine.tripple,
+            rustris: engine.rustris,
+            more_lines: engine.more_lines,
+            pieces: engine.pieces_locked,
+            level: match self.mode {
+                GameMode::Marathon | GameMode::Drill => engine.get_level(),
+                GameMode::Master => engine.master.level,
+            },
+            grade: match self.mode {
+                GameMode::Marathon | GameMode::Drill => None,
+                GameMode::Master => Some(engine.master.grade().to_string()),
+            },
+            finesse_faults: engine.finesse.faults,
+            targets_hit: match self.mode {
+                GameMode::Drill => Some(engine.finesse.targets_hit),
+                _ => None,
+            },
+            end,
+        })
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Simulation, String> {
+    let mut seed = None;
+    let mut mode = None;
+    let mut rules = None;
+    let mut piece_set = None;
+    let mut bot = None;
+    let mut tbp_bot = None;
+    let mut inputs = None;
+    let mut replay = None;
+    let mut max_frames = DEFAULT_MAX_FRAMES;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--seed" => {
+                let value = value()?;
+                seed = Some(
+                    u64::from_str_radix(value, 16)
+                        .map_err(|_| format!("invalid seed {}", value))?,
+                );
+            }
+            "--mode" => {
+                mode = Some(match value()? {
+                    "marathon" => GameMode::Marathon,
+                    "master" => GameMode::Master,
+                    "drill" => GameMode::Drill,
+                    other => return Err(format!("unknown mode {}", other)),
+                })
+            }
+            "--rules" => rules = Some(PathBuf::from(value()?)),
+            "--pieces" => piece_set = Some(String::from(value()?)),
+            "--bot" => bot = Some(String::from(value()?)),
+            "--tbp" => tbp_bot = Some(String::from(value()?)),
+            "--inputs" => inputs = Some(PathBuf::from(value()?)),
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--max-frames" => {
+                let value = value()?;
+                max_frames = value
+                    .parse()
+                    .map_err(|_| format!("invalid frame count {}", value))?;
+            }
+            "--help" | "-h" => return Err(String::from("Runs games without a window.")),
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+
+    let piece_sets = load_piece_sets();
+    let find_pieces = |name: &str| {
+        piece_sets
+            .iter()
+            .find(|set| set.name == name)
+            .cloned()
+            .ok_or_else(|| format!("unknown piece set {}", name))
+    };
+
+    //Replays bring everything needed to play them again.
+    if let Some(path) = replay {
+        if seed.is_some() || mode.is_some() || rules.is_some() || piece_set.is_some() {
+            return Err(String::from(
+                "a replay brings its own seed, mode, rules and piece set",
+            ));
+        }
+        if bot.is_some() || tbp_bot.is_some() || inputs.is_some() {
+            return Err(String::from(
+                "choose one of --bot, --tbp, --inputs and --replay",
+            ));
+        }
+        let replay = Replay::load(&path)?;
+        replay.options.validate()?;
+        let pieces = find_pieces(&replay.piece_set)?;
+        replay.options.validate_pieces(&pieces)?;
+        return Ok(Simulation {
+            seed: replay.seed,
+            mode: replay.mode,
+            options: replay.options,
+            pieces: Arc::new(pieces),
+            player: Player::Inputs(replay.inputs),
+            max_frames,
+        });
+    }
+
+    let seed = seed.unwrap_or_else(rand::random::<u64>);
+    let options = match rules {
+        Some(path) => {
+            let (config, errors) = config::load_config(&path);
+            if !errors.is_empty() {
+                return Err(format!("{}: {}", path.display(), errors.join(", ")));
+            }
+            config.game_options()
+        }
+        None => GameOptions::default(),
+    };
+    options.validate()?;
+    let pieces = find_pieces(piece_set.as_deref().unwrap_or("Standard"))?;
+    options.validate_pieces(&pieces)?;
+
+    let player = match (bot, tbp_bot, inputs) {
+        (Some(name), None, None) => Player::Bot(bot::named(&name, seed).ok_or_else(|| {
+            format!(
+                "unknown bot {}, expected one of {}",
+                name,
+                bot::BOT_NAMES.join(", ")
+            )
+        })?),
+        //The game waits for the bot to think, so it plays the same however fast it runs.
+        (None, Some(command), None) => {
+            tbp::supports(&options, &pieces)?;
+            let command = command
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            Player::Bot(Box::new(TbpBot::launch(&command, true)?))
+        }
+        (None, None, Some(path)) => Player::Inputs(read_inputs(&path)?),
+        _ => {
+            return Err(String::from(
+                "choose one of --bot, --tbp, --inputs and --replay",
+            ))
+        }
+    };
+
+    Ok(Simulation {
+        seed,
+        mode: mode.unwrap_or(GameMode::Marathon),
+        options,
+        pieces: Arc::new(pieces),
+        player,
+        max_frames,
+    })
+}
+
+fn load_piece_sets() -> Vec<PieceSet> {
+    let resource_dir = match env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => PathBuf::from(manifest_dir).join("resources"),
+        Err(_) => PathBuf::from("./resources"),
+    };
+    let (piece_sets, errors) = pieces::load_piece_sets(&resource_dir.join("pieces"));
+    errors
+        .iter()
+        .for_each(|e| eprintln!("Skipping piece set {}", e));
+    piece_sets
+}
+
+//Reads an input script: runs of inputs written as in replays, with `#` starting a comment that
+//goes to the end of the line.
+fn read_inputs(path: &Path) -> Result<Vec<FrameInput>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let script = contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .collect::<Vec<_>>()
+        .join(" ");
+    decode_inputs(&script, MAX_INPUT_FRAMES).map_err(|e| format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,690 @@
+//Plays Rustris in a terminal, so it can be played over SSH. It reads the same config, piece sets
+//and themes as the window, and saves replays and high scores in the same places.
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue, Result};
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustris::config::{self, Action, Config};
+use rustris::engine::*;
+use rustris::finesse::*;
+use rustris::master::*;
+use rustris::pieces::{self, PieceSet};
+use rustris::replay::Replay;
+use rustris::scores::*;
+use rustris::theme::{self, Theme};
+use rustris::types::*;
+
+//A key sent again sooner than this after it was pressed is pressed again, later it is the
+//terminal repeating it because it is held.
+const MIN_REPEAT_DELAY: u32 = 12;
+//A key that isn't repeated within this long of being pressed was let go.
+const MAX_REPEAT_DELAY: u32 = 40;
+//Frames without a repeat before a repeating key counts as let go.
+const REPEAT_GAP: u32 = 8;
+
+//Columns taken up by the score to the left of the grid.
+const HUD_WIDTH: u16 = 22;
+
+fn main() {
+    let resource_dir = match env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => PathBuf::from(manifest_dir).join("resources"),
+        Err(_) => PathBuf::from("./resources"),
+    };
+    let data_dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rustris");
+    let config_path = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rustris")
+        .join("config.toml");
+
+    let (piece_sets, errors) = pieces::load_piece_sets(&resource_dir.join("pieces"));
+    errors
+        .iter()
+        .for_each(|e| println!("Skipping piece set {}", e));
+    let (themes, errors) = theme::load_themes(&resource_dir.join("themes"));
+    errors.iter().for_each(|e| println!("Skipping theme {}", e));
+    let (config, errors) = config::load_config(&config_path);
+    if !errors.is_empty() {
+        println!(
+            "Using the default config, {} has errors:",
+            config_path.display()
+        );
+        errors.iter().for_each(|e| println!("  {}", e));
+    }
+
+    let pieces = piece_sets
+        .into_iter()
+        .find(|set| set.name == config.piece_set)
+        .unwrap_or_else(PieceSet::standard);
+    let theme = themes
+        .into_iter()
+        .find(|theme| theme.name == config.theme)
+        .unwrap_or_else(Theme::classic);
+    let mut game = Game::new(config, Arc::new(pieces), theme, data_dir);
+
+    let result = Terminal::open().and_then(|mut terminal| game.run(&mut terminal));
+    match result {
+        Ok(_) => println!("Exited cleanly."),
+        Err(e) => println!("Error occured: {}", e),
+    }
+}
+
+//Puts the terminal in raw mode on the alternate screen, and back the way it was when dropped,
+//even if the game panics.
+struct Terminal {
+    out: Stdout,
+}
+
+impl Terminal {
+    fn open() -> Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        Ok(Terminal { out })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Cell {
+    symbol: char,
+    fg: Color,
+    bg: Color,
+}
+
+const BLANK: Cell = Cell {
+    symbol: ' ',
+    fg: Color::Reset,
+    bg: Color::Reset,
+};
+
+//What the terminal should show. Only the cells that changed since the last frame are sent to
+//it, to keep the game playable over slow connections.
+struct Canvas {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+    shown: Vec<Cell>,
+}
+
+impl Canvas {
+    fn new((width, height): (u16, u16)) -> Canvas {
+        let size = width as usize * height as usize;
+        Canvas {
+            width,
+            height,
+            cells: vec![BLANK; size],
+            //Nothing is known to be on the screen yet, so all of it is sent.
+            shown: vec![
+                Cell {
+                    symbol: '\0',
+                    ..BLANK
+                };
+                size
+            ],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = BLANK);
+    }
+
+    //Writes text starting at (x, y). Text that goes past the edge is cut off.
+    fn print(&mut self, x: u16, y: u16, text: &str, fg: Color, bg: Color) {
+        if y >= self.height {
+            return;
+        }
+        for (index, symbol) in text.chars().enumerate() {
+            let x = x as usize + index;
+            if x >= self.width as usize {
+                break;
+            }
+            self.cells[y as usize * self.width as usize + x] = Cell { symbol, fg, bg };
+        }
+    }
+
+    //Writes lines of text one under the other.
+    fn print_lines(&mut self, x: u16, y: u16, text: &str) {
+        for (index, line) in text.lines().enumerate() {
+            self.print(x, y + index as u16, line, Color::Reset, Color::Reset);
+        }
+    }
+
+    fn flush(&mut self, out: &mut Stdout) -> Result<()> {
+        let mut last = None;
+        for (index, cell) in self.cells.iter().enumerate() {
+            if self.shown[index] == *cell {
+                continue;
+            }
+            let x = (index % self.width as usize) as u16;
+            let y = (index / self.width as usize) as u16;
+            queue!(out, MoveTo(x, y))?;
+            if last != Some((cell.fg, cell.bg)) {
+                queue!(
+                    out,
+                    SetForegroundColor(cell.fg),
+                    SetBackgroundColor(cell.bg)
+                )?;
+                last = Some((cell.fg, cell.bg));
+            }
+            queue!(out, Print(cell.symbol))?;
+        }
+        self.shown.copy_from_slice(&self.cells);
+        out.flush()?;
+        Ok(())
+    }
+}
+
+//A key the terminal sent, and what it looks like it is doing.
+struct HeldKey {
+    action: Action,
+    //Frames since the terminal last sent it.
+    frames: u32,
+    repeating: bool,
+}
+
+//Terminals can't tell when a key is let go. Instead they send a held key again and again, after
+//waiting as long as the keyboard's repeat delay. A key that was just pressed is held for a single
+//frame, and one that keeps repeating is held until the repeats stop, so auto shift starts after
+//the repeat delay and DAS both.
+struct Keys {
+    held: Vec<HeldKey>,
+}
+
+impl Keys {
+    fn new() -> Keys {
+        Keys { held: Vec::new() }
+    }
+
+    //Adds the key to the inputs of the next frame.
+    fn press(&mut self, action: Action, input: &mut FrameInput) {
+        let bit = match action.input() {
+            Some(bit) => bit,
+            None => return,
+        };
+        match self.held.iter_mut().find(|key| key.action == action) {
+            Some(key) if key.repeating => key.frames = 0,
+            Some(key) if key.frames >= MIN_REPEAT_DELAY => {
+                key.repeating = true;
+                key.frames = 0;
+            }
+            Some(key) => {
+                key.frames = 0;
+                input.press(bit);
+            }
+            None => {
+                self.held.push(HeldKey {
+                    action,
+                    frames: 0,
+                    repeating: false,
+                });
+                input.press(bit);
+            }
+        }
+        input.held |= bit;
+    }
+
+    //Moves on a frame, letting go of the keys that stopped repeating.
+    fn update(&mut self, input: &mut FrameInput) {
+        self.held.iter_mut().for_each(|key| key.frames += 1);
+        self.held.retain(|key| {
+            if key.repeating {
+                key.frames <= REPEAT_GAP
+            } else {
+                key.frames <= MAX_REPEAT_DELAY
+            }
+        });
+        input.held = self
+            .held
+            .iter()
+            .filter(|key| key.repeating)
+            .filter_map(|key| key.action.input())
+            .fold(0, |held, bit| held | bit);
+    }
+
+    fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+//Name the config file uses for a key, if it can be bound.
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char(',') => "Comma",
+        KeyCode::Char('.') => "Period",
+        KeyCode::Char('/') => "Slash",
+        KeyCode::Char('\\') => "Backslash",
+        KeyCode::Char(';') => "Semicolon",
+        KeyCode::Char('\'') => "Apostrophe",
+        KeyCode::Char('[') => "LBracket",
+        KeyCode::Char(']') => "RBracket",
+        KeyCode::Char('-') => "Minus",
+        KeyCode::Char('=') => "Equals",
+        KeyCode::Char('`') => "Grave",
+        KeyCode::Char(c) if c.is_ascii_digit() => return Some(format!("Key{}", c)),
+        KeyCode::Char(c) if c.is_ascii_alphabetic() => {
+            return Some(c.to_ascii_uppercase().to_string())
+        }
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Enter => "Return",
+        KeyCode::Esc => "Escape",
+        KeyCode::Backspace => "Back",
+        KeyCode::Tab | KeyCode::BackTab => "Tab",
+        KeyCode::Insert => "Insert",
+        KeyCode::Delete => "Delete",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        _ => return None,
+    };
+    Some(String::from(name))
+}
+
+fn terminal_color(color: rustris::types::Color, alpha: f32) -> Color {
+    let channel = |value: f32| (value * alpha * 255.0).round() as u8;
+    Color::Rgb {
+        r: channel(color.r),
+        g: channel(color.g),
+        b: channel(color.b),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Screen {
+    Menu,
+    Playing,
+    Paused,
+    //The stack of the game that just ended.
+    GameOver,
+}
+
+struct Game {
+    config: Config,
+    pieces: Arc<PieceSet>,
+    theme: Theme,
+    engine: Engine,
+    replay: Replay,
+    screen: Screen,
+    input: FrameInput,
+    keys: Keys,
+    last_result: Option<String>,
+    data_dir: PathBuf,
+    quit: bool,
+}
+
+impl Game {
+    fn new(config: Config, pieces: Arc<PieceSet>, theme: Theme, data_dir: PathBuf) -> Game {
+        Game {
+            engine: Engine::new(
+                GameMode::Marathon,
+                GameOptions::default(),
+                pieces.clone(),
+                0,
+            ),
+            replay: Replay::new(0, GameMode::Marathon, GameOptions::default(), ""),
+            config,
+            pieces,
+            theme,
+            screen: Screen::Menu,
+            input: FrameInput::default(),
+            keys: Keys::new(),
+            last_result: None,
+            data_dir,
+            quit: false,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut Terminal) -> Result<()> {
+        let mut canvas = Canvas::new(terminal::size()?);
+        let frame = Duration::from_secs(1) / FRAMES_PER_SECOND;
+        let mut next_frame = Instant::now();
+
+        while !self.quit {
+            while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+                match event::read()? {
+                    Event::Key(key) => self.key_down(key),
+                    Event::Resize(width, height) => {
+                        execute!(terminal.out, ResetColor, Clear(ClearType::All))?;
+                        canvas = Canvas::new((width, height));
+                    }
+                    Event::Mouse(_) => (),
+                }
+            }
+            next_frame += frame;
+            //Frames are skipped rather than played all at once if the terminal falls behind.
+            if next_frame < Instant::now() {
+                next_frame = Instant::now() + frame;
+            }
+
+            if self.screen == Screen::Playing {
+                self.update();
+            }
+            canvas.clear();
+            self.draw(&mut canvas);
+            canvas.flush(&mut terminal.out)?;
+        }
+        Ok(())
+    }
+
+    fn start_game(&mut self, mode: GameMode) {
+        let options = self.config.game_options();
+        if let Err(e) = options
+            .validate()
+            .and_then(|_| options.validate_pieces(&self.pieces))
+        {
+            self.last_result = Some(format!("Can't start the game: {}", e));
+            return;
+        }
+        let seed = rand::random::<u64>();
+        self.replay = Replay::new(seed, mode, options, &self.pieces.name);
+        self.engine = Engine::new(mode, options, self.pieces.clone(), seed);
+        self.engine.start();
+        self.input = FrameInput::default();
+        self.keys.clear();
+        self.screen = Screen::Playing;
+    }
+
+    fn update(&mut self) {
+        let input = self.input;
+        self.input.pressed = 0;
+        self.replay.record(input);
+        self.engine.tick(input);
+        self.keys.update(&mut self.input);
+        if self.engine.is_game_over() {
+            self.end_game();
+        }
+    }
+
+    //Saves the replay and, if it made the leaderboard, the score under the player's user name.
+    fn end_game(&mut self) {
+        let summary = self.engine.summary();
+        self.replay.summary = summary.clone();
+        let mut result = summary;
+        if let Err(e) = self.replay.save_new(&self.data_dir.join("replays")) {
+            result = format!("{}\nCouldn't save the replay: {}", result, e);
+        }
+
+        let scores_path = self.data_dir.join("scores.toml");
+        let mut entry = ScoreEntry::from_game(&self.engine, self.replay.seed);
+        entry.name = env::var("USER").unwrap_or_else(|_| String::from("Anonymous"));
+        match HighScores::load(&scores_path) {
+            Ok(mut high_scores) if high_scores.qualifies(self.engine.mode, &entry) => {
+                if let Some(place) = high_scores.insert(self.engine.mode, entry) {
+                    result = format!("{}\nNew high score, number {}!", result, place + 1);
+                }
+                if let Err(e) = high_scores.save(&scores_path) {
+                    result = format!("{}\nCouldn't save the high scores: {}", result, e);
+                }
+            }
+            Ok(_) => (),
+            Err(e) => result = format!("{}\nCouldn't load the high scores: {}", result, e),
+        }
+
+        self.last_result = Some(result);
+        self.screen = Screen::GameOver;
+    }
+
+    fn key_down(&mut self, key: KeyEvent) {
+        //Raw mode swallows Ctrl+C, so it is handled here.
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        let action = key_name(key.code).and_then(|name| self.config.bindings.action(&name));
+
+        match self.screen {
+            Screen::Menu => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Char('m') => self.start_game(GameMode::Master),
+                KeyCode::Char('f') => self.start_game(GameMode::Drill),
+                _ => self.start_game(GameMode::Marathon),
+            },
+            Screen::Playing => match action {
+                Some(Action::Quit) => {
+                    self.last_result = Some(String::from("Game abandoned."));
+                    self.screen = Screen::Menu;
+                }
+                Some(Action::Pause) => {
+                    self.keys.clear();
+                    self.input = FrameInput::default();
+                    self.screen = Screen::Paused;
+                }
+                Some(action) => self.keys.press(action, &mut self.input),
+                None => (),
+            },
+            Screen::Paused => match action {
+                Some(Action::Pause) => self.screen = Screen::Playing,
+                Some(Action::Quit) => self.screen = Screen::Menu,
+                _ => (),
+            },
+            Screen::GameOver => self.screen = Screen::Menu,
+        }
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        match self.screen {
+            Screen::Menu => {
+                let mut text = format!(
+                    "RUSTRIS\n\nPieces: {}\nBoard: {}x{}\n\nPress M to start master mode.\nPress F to start the finesse drill.\nPress Q to quit.\nPress any other key to start.",
+                    self.pieces.name, self.config.rules.width, self.config.rules.height
+                );
+                if let Some(result) = &self.last_result {
+                    text = format!("{}\n\n{}", result, text);
+                }
+                canvas.print_lines(2, 1, &text);
+            }
+            Screen::Paused => {
+                self.draw_score(canvas);
+                let keys = self.config.bindings.keys(Action::Pause).join(", ");
+                canvas.print_lines(
+                    HUD_WIDTH,
+                    2,
+                    &format!("Paused\n\nPress {} to continue.", keys),
+                );
+            }
+            Screen::Playing | Screen::GameOver => {
+                let rows = self.engine.options.height as u16;
+                let columns = self.engine.grid.width as u16;
+                let needed = (HUD_WIDTH + 2 * columns + 16, rows + 3);
+                if canvas.width < needed.0 || canvas.height < needed.1 {
+                    canvas.print_lines(
+                        0,
+                        0,
+                        &format!(
+                            "Make the terminal at least\n{}x{} to see the game.",
+                            needed.0, needed.1
+                        ),
+                    );
+                    return;
+                }
+                self.draw_score(canvas);
+                self.draw_grid(canvas);
+                let panel = HUD_WIDTH + 2 * columns + 4;
+                canvas.print(panel, 1, "Next", Color::Reset, Color::Reset);
+                if let Some(tet) = self.engine.tetromino_next.as_ref() {
+                    self.draw_preview(canvas, tet, panel, 2);
+                }
+                canvas.print(panel, 8, "Hold", Color::Reset, Color::Reset);
+                if let Some(tet) = self.engine.tetromino_hold.as_ref() {
+                    self.draw_preview(canvas, tet, panel, 9);
+                }
+                if self.screen == Screen::GameOver {
+                    canvas.print_lines(
+                        0,
+                        rows + 3,
+                        &format!(
+                            "{}\nPress any key to continue.",
+                            self.last_result.as_ref().unwrap()
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn draw_score(&self, canvas: &mut Canvas) {
+        let engine = &self.engine;
+        let text = match engine.mode {
+            GameMode::Marathon => format!(
+                "Score: {}\n\nLevel: {}\n\nLine Clears:\n  Single: {}\n  Double: {}\n  Tripple: {}\n  Rustris: {}\n  5+ lines: {}\n\nFinesse faults: {}",
+                engine.score, engine.get_level(), engine.single, engine.double, engine.tripple, engine.rustris, engine.more_lines, engine.finesse.faults
+            ),
+            GameMode::Master => format!(
+                "Grade: {}\n\nScore: {}\n\nLevel: {:03}\n       {:03}\n\nTime: {}",
+                engine.master.grade(),
+                engine.master.score,
+                engine.master.level,
+                engine.master.level_stop(),
+                format_time(engine.frame)
+            ),
+            GameMode::Drill => format!(
+                "Drill\n\nTargets hit: {}\n         of {}\n\nFinesse faults: {}\n\nTime: {}\n\n{}",
+                engine.finesse.targets_hit,
+                DRILL_PIECES,
+                engine.finesse.faults,
+                format_time(engine.frame),
+                engine.finesse.last.as_ref().map_or_else(String::new, Judgement::describe)
+            ),
+        };
+        canvas.print_lines(1, 1, &text);
+    }
+
+    //Draws the visible part of the grid in a frame, with every block two columns wide so they
+    //come out about square.
+    fn draw_grid(&self, canvas: &mut Canvas) {
+        let engine = &self.engine;
+        let hidden_rows = engine.options.hidden_rows;
+        let columns = engine.grid.width as u16;
+        let rows = engine.options.height as u16;
+        let left = HUD_WIDTH;
+        let top = 1;
+
+        for y in 0..rows {
+            canvas.print(left, top + y, "|", Color::Reset, Color::Reset);
+            canvas.print(
+                left + 1 + 2 * columns,
+                top + y,
+                "|",
+                Color::Reset,
+                Color::Reset,
+            );
+            for x in 0..columns {
+                canvas.print(
+                    left + 1 + 2 * x,
+                    top + y,
+                    " .",
+                    Color::DarkGrey,
+                    Color::Reset,
+                );
+            }
+        }
+        let bottom = format!("+{}+", "-".repeat(2 * columns as usize));
+        canvas.print(left, top + rows, &bottom, Color::Reset, Color::Reset);
+
+        //The drill's target, under the tetromino in play.
+        if let Some(target) = engine.finesse.target.as_ref() {
+            for block in target.blocks(&engine.pieces) {
+                let x = target.position.x + block.position.x;
+                let y = target.position.y + block.position.y;
+                if y < hidden_rows as f32 {
+                    continue;
+                }
+                let color = self.theme.block_color(&engine.pieces, &block);
+                canvas.print(
+                    left + 1 + 2 * x as u16,
+                    top + (y as u32 - hidden_rows) as u16,
+                    "::",
+                    terminal_color(color, 1.0),
+                    Color::Reset,
+                );
+            }
+        }
+
+        let mut blocks = engine
+            .grid
+            .blocks
+            .iter()
+            .flat_map(|column| column.iter())
+            .filter(|block| block.state == BlockState::Filled)
+            .map(|block| {
+                (
+                    block.position.x,
+                    block.position.y,
+                    *block,
+                    engine.block_look(block),
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Some(tet) = engine.tetromino.as_ref() {
+            blocks.extend(tet.blocks(&engine.pieces).into_iter().map(|block| {
+                (
+                    tet.position.x + block.position.x,
+                    tet.position.y + block.position.y,
+                    block,
+                    BlockLook::Solid(1.0),
+                )
+            }));
+        }
+
+        for (x, y, block, look) in blocks {
+            if y < hidden_rows as f32 {
+                continue;
+            }
+            let color = self.theme.block_color(&engine.pieces, &block);
+            let column = left + 1 + 2 * x as u16;
+            let row = top + (y as u32 - hidden_rows) as u16;
+            match look {
+                BlockLook::Solid(alpha) => canvas.print(
+                    column,
+                    row,
+                    "  ",
+                    Color::Reset,
+                    terminal_color(color, alpha),
+                ),
+                BlockLook::Outline => {
+                    canvas.print(column, row, "[]", terminal_color(color, 1.0), Color::Reset)
+                }
+                BlockLook::Hidden => (),
+            }
+        }
+    }
+
+    //Draws a tetromino at its normal size in a box whose top left corner is at (left, top).
+    fn draw_preview(&self, canvas: &mut Canvas, tet: &Tetromino, left: u16, top: u16) {
+        let tet = Tetromino { scale: 1, ..*tet };
+        for block in tet.blocks(&self.engine.pieces) {
+            let x = 2 + block.position.x as i32;
+            let y = 2 + block.position.y as i32;
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let color = self.theme.block_color(&self.engine.pieces, &block);
+            canvas.print(
+                left + 2 * x as u16,
+                top + y as u16,
+                "  ",
+                Color::Reset,
+                terminal_color(color, 1.0),
+            );
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::ai::{AiBot, DIFFICULTIES};
+use crate::engine::*;
+use crate::random::Random;
+
+//Names of the bots `named` knows. The AI goes by `ai-` and its difficulty.
+pub const BOT_NAMES: [&str; 6] = [
+    "idle",
+    "random",
+    "ai-easy",
+    "ai-medium",
+    "ai-hard",
+    "ai-max",
+];
+
+//Plays a game by itself, a frame at a time, seeing the game the same way a player would.
+pub trait Bot {
+    //Who is playing, to show next to the game.
+    fn name(&self) -> String;
+
+    //Inputs for the next frame of the game.
+    fn next_input(&mut self, engine: &Engine) -> FrameInput;
+}
+
+//Looks up one of the built in bots. Bots that make random choices make them from `seed`, so
+//they play the same game every time.
+pub fn named(name: &str, seed: u64) -> Option<Box<dyn Bot>> {
+    match name {
+        "idle" => Some(Box::new(IdleBot)),
+        "random" => Some(Box::new(RandomBot::new(seed))),
+        _ => {
+            let difficulty = name.strip_prefix("ai-")?;
+            DIFFICULTIES
+                .iter()
+                .find(|known| known.name() == difficulty)
+                .map(|difficulty| Box::new(AiBot::new(*difficulty)) as Box<dyn Bot>)
+        }
+    }
+}
+
+//Never touches anything, so the pieces stack up where they spawn.
+pub struct IdleBot;
+
+impl Bot for IdleBot {
+    fn name(&self) -> String {
+        String::from("idle")
+    }
+
+    fn next_input(&mut self, _engine: &Engine) -> FrameInput {
+        FrameInput::default()
+    }
+}
+
+//Mashes random buttons, a few times a second.
+pub struct RandomBot {
+    random: Random,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> RandomBot {
+        RandomBot {
+            random: Random::new(seed),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn name(&self) -> String {
+        String::from("random")
+    }
+
+    fn next_input(&mut self, _engine: &Engine) -> FrameInput {
+        let mut input = FrameInput::default();
+        if self.random.below(8) == 0 {
+            let bit = 1 << self.random.below(8);
+            input.press(bit);
+            input.held = bit;
+        }
+        input
+    }
+}
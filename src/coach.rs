@@ -0,0 +1,104 @@
+use crate::ai::Board;
+use crate::engine::*;
+use crate::types::*;
+
+//Placements listed after a game. The rest are only counted.
+const MISTAKES_SHOWN: usize = 8;
+
+//A placement that left holes in the stack.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mistake {
+    //Which tetromino of the game it was, counting from 1.
+    pub number: u32,
+    pub piece: String,
+    //Leftmost column the tetromino ended up in, counting from 1.
+    pub column: u32,
+    pub holes: u32,
+}
+
+//Watches a game for placements that leave holes in the stack, to go over once it ends.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coach {
+    //Holes in the stack after the last tetromino locked.
+    holes: u32,
+    pub mistakes: Vec<Mistake>,
+}
+
+impl Coach {
+    pub fn new() -> Coach {
+        Coach::default()
+    }
+
+    //Looks at the stack after every frame of the game, and at any tetromino that just locked.
+    pub fn update(&mut self, engine: &Engine) {
+        if !engine.events().contains(&GameEvent::Lock) {
+            return;
+        }
+        //Filled rows are still on the grid during the line clear delay.
+        let mut board = Board::from_grid(&engine.grid);
+        board.clear_full_rows();
+        let holes = board.holes();
+
+        if holes > self.holes {
+            let locked = engine
+                .grid
+                .blocks
+                .iter()
+                .flatten()
+                .filter(|block| {
+                    block.state == BlockState::Filled && block.locked_at == engine.frame
+                })
+                .collect::<Vec<_>>();
+            let piece = locked
+                .iter()
+                .find_map(|block| block.piece)
+                .and_then(|piece| engine.pieces.pieces.get(piece))
+                .map_or_else(|| String::from("?"), |piece| piece.name.clone());
+            let column = locked
+                .iter()
+                .map(|block| block.position.x as u32 + 1)
+                .min()
+                .unwrap_or(0);
+            self.mistakes.push(Mistake {
+                number: engine.pieces_locked,
+                piece,
+                column,
+                holes: holes - self.holes,
+            });
+        }
+        self.holes = holes;
+    }
+
+    //Lists the placements that left holes.
+    pub fn summary(&self) -> String {
+        if self.mistakes.is_empty() {
+            return String::from("No placement left a hole. Well stacked!");
+        }
+        let mut lines = self
+            .mistakes
+            .iter()
+            .take(MISTAKES_SHOWN)
+            .map(|mistake| {
+                format!(
+                    "  Piece {} ({}) in column {}: {} new hole{}",
+                    mistake.number,
+                    mistake.piece,
+                    mistake.column,
+                    mistake.holes,
+                    if mistake.holes == 1 { "" } else { "s" }
+                )
+            })
+            .collect::<Vec<_>>();
+        if self.mistakes.len() > MISTAKES_SHOWN {
+            lines.push(format!(
+                "  and {} more",
+                self.mistakes.len() - MISTAKES_SHOWN
+            ));
+        }
+        format!(
+            "{} placements left holes:\n{}",
+            self.mistakes.len(),
+            lines.join("\n")
+        )
+    }
+}
@@ -0,0 +1,759 @@
+#[cfg(feature = "window")]
+use ggez::conf::{FullscreenType, WindowMode};
+#[cfg(feature = "window")]
+use ggez::event::Button;
+#[cfg(feature = "window")]
+use ggez::input::keyboard::KeyCode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::ai::Difficulty;
+use crate::engine::*;
+
+pub const MIN_WINDOW_WIDTH: u32 = 320;
+pub const MIN_WINDOW_HEIGHT: u32 = 320;
+pub const MIN_BLOCK_SIZE: u32 = 10;
+pub const MAX_BLOCK_SIZE: u32 = 60;
+pub const MAX_DEADZONE: f32 = 0.9;
+
+//Keys that can be bound, by the names used in the config file. These are the names of the keys'
+//`KeyCode`s in the window.
+pub const KEY_NAMES: &[&str] = &[
+    "A",
+    "B",
+    "C",
+    "D",
+    "E",
+    "F",
+    "G",
+    "H",
+    "I",
+    "J",
+    "K",
+    "L",
+    "M",
+    "N",
+    "O",
+    "P",
+    "Q",
+    "R",
+    "S",
+    "T",
+    "U",
+    "V",
+    "W",
+    "X",
+    "Y",
+    "Z",
+    "Key0",
+    "Key1",
+    "Key2",
+    "Key3",
+    "Key4",
+    "Key5",
+    "Key6",
+    "Key7",
+    "Key8",
+    "Key9",
+    "Numpad0",
+    "Numpad1",
+    "Numpad2",
+    "Numpad3",
+    "Numpad4",
+    "Numpad5",
+    "Numpad6",
+    "Numpad7",
+    "Numpad8",
+    "Numpad9",
+    "Left",
+    "Right",
+    "Up",
+    "Down",
+    "Space",
+    "Return",
+    "Escape",
+    "Back",
+    "Tab",
+    "LShift",
+    "RShift",
+    "LControl",
+    "RControl",
+    "LAlt",
+    "RAlt",
+    "Insert",
+    "Delete",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "Comma",
+    "Period",
+    "Slash",
+    "Backslash",
+    "Semicolon",
+    "Apostrophe",
+    "LBracket",
+    "RBracket",
+    "Minus",
+    "Equals",
+    "Grave",
+];
+
+//Gamepad buttons that can be bound, by the names used in the config file, which are the names of
+//their `Button`s.
+pub const BUTTON_NAMES: &[&str] = &[
+    "South",
+    "East",
+    "North",
+    "West",
+    "C",
+    "Z",
+    "LeftTrigger",
+    "LeftTrigger2",
+    "RightTrigger",
+    "RightTrigger2",
+    "Select",
+    "Start",
+    "Mode",
+    "LeftThumb",
+    "RightThumb",
+    "DPadUp",
+    "DPadDown",
+    "DPadLeft",
+    "DPadRight",
+];
+
+#[cfg(feature = "window")]
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    let name = format!("{:?}", key);
+    KEY_NAMES.iter().find(|other| **other == name).copied()
+}
+
+#[cfg(feature = "window")]
+pub fn button_name(button: Button) -> Option<&'static str> {
+    let name = format!("{:?}", button);
+    BUTTON_NAMES.iter().find(|other| **other == name).copied()
+}
+
+//Where the score and previews go around the grid.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    //Whichever of the others fits the window best.
+    Auto,
+    //Score to the left of the grid and previews to its right.
+    Landscape,
+    //Previews above the grid and the score under it.
+    Portrait,
+}
+
+impl Orientation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Auto => "auto",
+            Orientation::Landscape => "landscape",
+            Orientation::Portrait => "portrait",
+        }
+    }
+
+    pub fn next(&self) -> Orientation {
+        match self {
+            Orientation::Auto => Orientation::Landscape,
+            Orientation::Landscape => Orientation::Portrait,
+            Orientation::Portrait => Orientation::Auto,
+        }
+    }
+}
+
+//Everything keys and buttons can be bound to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Hold,
+    Pause,
+    Quit,
+}
+
+pub const ACTIONS: [Action; 10] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::SoftDrop,
+    Action::HardDrop,
+    Action::RotateCw,
+    Action::RotateCcw,
+    Action::Rotate180,
+    Action::Hold,
+    Action::Pause,
+    Action::Quit,
+];
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::SoftDrop => "Soft drop",
+            Action::HardDrop => "Hard drop",
+            Action::RotateCw => "Rotate clockwise",
+            Action::RotateCcw => "Rotate counterclockwise",
+            Action::Rotate180 => "Rotate 180",
+            Action::Hold => "Hold",
+            Action::Pause => "Pause",
+            Action::Quit => "Quit",
+        }
+    }
+
+    //The input bit the action makes in a game. Pausing and quitting happen outside of the game.
+    pub fn input(&self) -> Option<u8> {
+        match self {
+            Action::MoveLeft => Some(INPUT_LEFT),
+            Action::MoveRight => Some(INPUT_RIGHT),
+            Action::SoftDrop => Some(INPUT_DOWN),
+            Action::HardDrop => Some(INPUT_HARD_DROP),
+            Action::RotateCw => Some(INPUT_ROTATE_CW),
+            Action::RotateCcw => Some(INPUT_ROTATE_CCW),
+            Action::Rotate180 => Some(INPUT_ROTATE_180),
+            Action::Hold => Some(INPUT_HOLD),
+            Action::Pause | Action::Quit => None,
+        }
+    }
+}
+
+//Everything that can be set in the config file. Missing settings keep their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    //Name of the piece set games start with.
+    pub piece_set: String,
+    //Name of the theme the game is drawn with.
+    pub theme: String,
+    pub handling: Handling,
+    pub rules: Rules,
+    pub display: Display,
+    pub audio: Audio,
+    pub effects: Effects,
+    pub bindings: Bindings,
+    pub gamepad: Gamepad,
+    pub ai: Ai,
+    pub versus: Versus,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    pub das: u32,
+    pub arr: u32,
+    pub sdf: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    pub width: u32,
+    pub height: u32,
+    pub hidden_rows: u32,
+    pub are: u32,
+    pub line_clear_delay: u32,
+    pub big: bool,
+    pub stack: StackVisibility,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Display {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    //Size of a block on the grid, in pixels, before the game is scaled to fit the window. Smaller
+    //blocks make the text larger next to the grid.
+    pub block_size: u32,
+    pub orientation: Orientation,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    //Volumes go from 0.0 (muted) to 1.0. The music and sound effects are both scaled by `volume`.
+    pub volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    //Whether the music gets faster as the game goes on.
+    pub music_speedup: bool,
+}
+
+//Visual effects. They can all be turned off at once with `enabled`, for competitive play.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Effects {
+    pub enabled: bool,
+    //Filled rows flash and collapse during the line clear delay.
+    pub line_clear: bool,
+    //Blocks flash as they lock.
+    pub lock_flash: bool,
+    //Sparks fly off rustrises and T-spins.
+    pub particles: bool,
+    pub level_up: bool,
+    pub screen_shake: bool,
+}
+
+//The computer player, and the hints it gives.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ai {
+    pub difficulty: Difficulty,
+    //Command that starts a bot speaking the Tetris Bot Protocol, as the program followed by its
+    //arguments.
+    pub tbp_bot: Vec<String>,
+    //Outline where the AI would put the falling tetromino, and list the placements that left holes
+    //after the game.
+    pub hints: bool,
+}
+
+//Two player games on one keyboard. Player 1 plays with the usual bindings and the first gamepad
+//used, player 2 with these and any other gamepad.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Versus {
+    pub bindings: Bindings,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gamepad {
+    //How far a stick has to be pushed before it counts, from 0.0 to `MAX_DEADZONE`.
+    pub deadzone: f32,
+    //Bound by the names in `BUTTON_NAMES`.
+    pub buttons: Bindings,
+}
+
+//Keys or buttons bound to each action, by name. See `KEY_NAMES` for the names of the keys. The
+//defaults are only used when the whole table is missing, actions left out of it are unbound.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bindings {
+    #[serde(default)]
+    pub left: Vec<String>,
+    #[serde(default)]
+    pub right: Vec<String>,
+    #[serde(default)]
+    pub soft_drop: Vec<String>,
+    #[serde(default)]
+    pub hard_drop: Vec<String>,
+    #[serde(default)]
+    pub rotate_cw: Vec<String>,
+    #[serde(default)]
+    pub rotate_ccw: Vec<String>,
+    #[serde(default)]
+    pub rotate_180: Vec<String>,
+    #[serde(default)]
+    pub hold: Vec<String>,
+    #[serde(default)]
+    pub pause: Vec<String>,
+    #[serde(default)]
+    pub quit: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            piece_set: String::from("Standard"),
+            theme: String::from("Classic"),
+            handling: Handling::default(),
+            rules: Rules::default(),
+            display: Display::default(),
+            audio: Audio::default(),
+            effects: Effects::default(),
+            bindings: Bindings::default(),
+            gamepad: Gamepad::default(),
+            ai: Ai::default(),
+            versus: Versus::default(),
+        }
+    }
+}
+
+impl Default for Handling {
+    fn default() -> Handling {
+        let options = GameOptions::default();
+        Handling {
+            das: options.das,
+            arr: options.arr,
+            sdf: options.sdf,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        let options = GameOptions::default();
+        Rules {
+            width: options.width,
+            height: options.height,
+            hidden_rows: options.hidden_rows,
+            are: options.are,
+            line_clear_delay: options.line_clear_delay,
+            big: options.big,
+            stack: options.stack,
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            block_size: 25,
+            orientation: Orientation::Auto,
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            volume: 1.0,
+            music_volume: 0.5,
+            effects_volume: 1.0,
+            music_speedup: true,
+        }
+    }
+}
+
+impl Default for Effects {
+    fn default() -> Effects {
+        Effects {
+            enabled: true,
+            line_clear: true,
+            lock_flash: true,
+            particles: true,
+            level_up: true,
+            screen_shake: false,
+        }
+    }
+}
+
+impl Default for Ai {
+    fn default() -> Ai {
+        Ai {
+            difficulty: Difficulty::Medium,
+            tbp_bot: Vec::new(),
+            hints: false,
+        }
+    }
+}
+
+//Player 2's keys stay clear of player 1's.
+impl Default for Versus {
+    fn default() -> Versus {
+        Versus {
+            bindings: Bindings {
+                left: keys(&["J"]),
+                right: keys(&["L"]),
+                soft_drop: keys(&["K"]),
+                hard_drop: keys(&["I"]),
+                rotate_cw: keys(&["O"]),
+                rotate_ccw: keys(&["U"]),
+                rotate_180: keys(&["Key9"]),
+                hold: keys(&["Semicolon"]),
+                pause: Vec::new(),
+                quit: Vec::new(),
+            },
+        }
+    }
+}
+
+fn keys(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            left: keys(&["Left"]),
+            right: keys(&["Right"]),
+            soft_drop: keys(&["Down"]),
+            hard_drop: keys(&["Space"]),
+            rotate_cw: keys(&["Up", "X"]),
+            rotate_ccw: keys(&["Z"]),
+            rotate_180: keys(&["A"]),
+            hold: keys(&["C", "LShift"]),
+            pause: keys(&["P"]),
+            quit: keys(&["Escape"]),
+        }
+    }
+}
+
+impl Default for Gamepad {
+    fn default() -> Gamepad {
+        Gamepad {
+            deadzone: 0.5,
+            buttons: Bindings {
+                left: keys(&["DPadLeft"]),
+                right: keys(&["DPadRight"]),
+                soft_drop: keys(&["DPadDown"]),
+                hard_drop: keys(&["DPadUp"]),
+                rotate_cw: keys(&["East"]),
+                rotate_ccw: keys(&["South"]),
+                rotate_180: keys(&["North"]),
+                hold: keys(&["LeftTrigger", "RightTrigger"]),
+                pause: keys(&["Start"]),
+                quit: keys(&["Select"]),
+            },
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys(&self, action: Action) -> &Vec<String> {
+        match action {
+            Action::MoveLeft => &self.left,
+            Action::MoveRight => &self.right,
+            Action::SoftDrop => &self.soft_drop,
+            Action::HardDrop => &self.hard_drop,
+            Action::RotateCw => &self.rotate_cw,
+            Action::RotateCcw => &self.rotate_ccw,
+            Action::Rotate180 => &self.rotate_180,
+            Action::Hold => &self.hold,
+            Action::Pause => &self.pause,
+            Action::Quit => &self.quit,
+        }
+    }
+
+    pub fn keys_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::MoveLeft => &mut self.left,
+            Action::MoveRight => &mut self.right,
+            Action::SoftDrop => &mut self.soft_drop,
+            Action::HardDrop => &mut self.hard_drop,
+            Action::RotateCw => &mut self.rotate_cw,
+            Action::RotateCcw => &mut self.rotate_ccw,
+            Action::Rotate180 => &mut self.rotate_180,
+            Action::Hold => &mut self.hold,
+            Action::Pause => &mut self.pause,
+            Action::Quit => &mut self.quit,
+        }
+    }
+
+    //The action the key or button with the given name is bound to. One bound to several actions
+    //does the first of them.
+    pub fn action(&self, name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|action| self.keys(**action).iter().any(|other| other == name))
+            .copied()
+    }
+
+    //Binds another key or button to the action.
+    pub fn bind(&mut self, action: Action, name: &str) {
+        let keys = self.keys_mut(action);
+        if !keys.iter().any(|other| other == name) {
+            keys.push(String::from(name));
+        }
+    }
+
+    //Describes every key or button that is bound to more than one action.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut names = ACTIONS
+            .iter()
+            .flat_map(|action| self.keys(*action).iter())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let actions = ACTIONS
+                    .iter()
+                    .filter(|action| self.keys(**action).contains(name))
+                    .map(|action| action.name())
+                    .collect::<Vec<_>>();
+                if actions.len() > 1 {
+                    Some(format!("{} is bound to {}", name, actions.join(" and ")))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    //Describes every key bound both here, for player 1, and in `other`, for player 2.
+    pub fn conflicts_with(&self, other: &Bindings) -> Vec<String> {
+        ACTIONS
+            .iter()
+            .flat_map(|action| other.keys(*action).iter().map(move |name| (action, name)))
+            .filter_map(|(action, name)| {
+                self.action(name).map(|own| {
+                    format!(
+                        "{} is bound to {} for player 1 and {} for player 2",
+                        name,
+                        own.name(),
+                        action.name()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl Config {
+    //Options new games are played with.
+    pub fn game_options(&self) -> GameOptions {
+        GameOptions {
+            width: self.rules.width,
+            height: self.rules.height,
+            hidden_rows: self.rules.hidden_rows,
+            are: self.rules.are,
+            line_clear_delay: self.rules.line_clear_delay,
+            big: self.rules.big,
+            stack: self.rules.stack,
+            das: self.handling.das,
+            arr: self.handling.arr,
+            sdf: self.handling.sdf,
+        }
+    }
+
+    #[cfg(feature = "window")]
+    pub fn window_mode(&self) -> WindowMode {
+        WindowMode {
+            width: self.display.width as f32,
+            height: self.display.height as f32,
+            maximized: false,
+            fullscreen_type: if self.display.fullscreen {
+                FullscreenType::Desktop
+            } else {
+                FullscreenType::Windowed
+            },
+            borderless: false,
+            min_width: MIN_WINDOW_WIDTH as f32,
+            max_width: 0.0,
+            min_height: MIN_WINDOW_HEIGHT as f32,
+            max_height: 0.0,
+            resizable: true,
+        }
+    }
+
+    //Lists everything wrong with the config.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.game_options().validate() {
+            errors.push(e);
+        }
+        if self.display.width < MIN_WINDOW_WIDTH || self.display.height < MIN_WINDOW_HEIGHT {
+            errors.push(format!(
+                "the window must be at least {}x{}, got {}x{}",
+                MIN_WINDOW_WIDTH, MIN_WINDOW_HEIGHT, self.display.width, self.display.height
+            ));
+        }
+        if self.display.block_size < MIN_BLOCK_SIZE || self.display.block_size > MAX_BLOCK_SIZE {
+            errors.push(format!(
+                "block_size must be between {} and {}, got {}",
+                MIN_BLOCK_SIZE, MAX_BLOCK_SIZE, self.display.block_size
+            ));
+        }
+        for (name, volume) in [
+            ("volume", self.audio.volume),
+            ("music_volume", self.audio.music_volume),
+            ("effects_volume", self.audio.effects_volume),
+        ]
+        .iter()
+        {
+            if !(0.0..=1.0).contains(volume) {
+                errors.push(format!(
+                    "{} must be between 0.0 and 1.0, got {}",
+                    name, volume
+                ));
+            }
+        }
+        if !(0.0..=MAX_DEADZONE).contains(&self.gamepad.deadzone) {
+            errors.push(format!(
+                "deadzone must be between 0.0 and {}, got {}",
+                MAX_DEADZONE, self.gamepad.deadzone
+            ));
+        }
+        for action in ACTIONS.iter() {
+            let keys = self.bindings.keys(*action).iter();
+            for name in keys.chain(self.versus.bindings.keys(*action).iter()) {
+                if !KEY_NAMES.contains(&name.as_str()) {
+                    errors.push(format!(
+                        "{} is bound to unknown key {}",
+                        action.name(),
+                        name
+                    ));
+                }
+            }
+            for name in self.gamepad.buttons.keys(*action).iter() {
+                if !BUTTON_NAMES.contains(&name.as_str()) {
+                    errors.push(format!(
+                        "{} is bound to unknown button {}",
+                        action.name(),
+                        name
+                    ));
+                }
+            }
+        }
+        errors.append(&mut self.bindings.conflicts_with(&self.versus.bindings));
+        errors
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+//Loads the config file. If it is missing the defaults are used, and if anything in it is wrong
+//the problems are reported back and the defaults are used instead.
+pub fn load_config(path: &Path) -> (Config, Vec<String>) {
+    if !path.exists() {
+        return (Config::default(), Vec::new());
+    }
+    let config = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| toml::from_str::<Config>(&contents).map_err(|e| e.to_string()));
+
+    match config {
+        Ok(config) => {
+            let errors = config.validate();
+            if errors.is_empty() {
+                (config, errors)
+            } else {
+                (Config::default(), errors)
+            }
+        }
+        Err(e) => (Config::default(), vec![e]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Config::default().validate().is_empty());
+    }
+
+    #[test]
+    fn keys_shared_by_both_players_are_reported() {
+        let mut config = Config::default();
+        config.bindings.bind(Action::Hold, "J");
+        assert_eq!(
+            config.validate(),
+            vec![String::from(
+                "J is bound to Hold for player 1 and Move left for player 2"
+            )]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use ggez::graphics;
+use ggez::graphics::Color;
+use ggez::graphics::*;
+use ggez::nalgebra::geometry::Point2;
+use ggez::{Context, GameResult};
+
+use crate::config::Effects;
+use crate::engine::*;
+use crate::style::Style;
+use crate::types::*;
+
+//Frames locked blocks flash for.
+const LOCK_FLASH_FRAMES: u32 = 8;
+
+const PARTICLE_FRAMES: u32 = 40;
+const PARTICLES_PER_BLOCK: usize = 4;
+//Pull on the particles, in pixels per frame per frame.
+const PARTICLE_GRAVITY: f32 = 0.3;
+
+const SHAKE_FRAMES: u32 = 12;
+const BANNER_FRAMES: u32 = 90;
+
+struct Particle {
+    x: f32,
+    y: f32,
+    speed_x: f32,
+    speed_y: f32,
+    frames: u32,
+    color: Color,
+}
+
+//Animations drawn over the grid. They only look at the game and never change it, so replays and
+//games play the same with or without them.
+pub struct EffectLayer {
+    config: Effects,
+    particles: Vec<Particle>,
+    shake_frames: u32,
+    shake_strength: f32,
+    offset: (f32, f32),
+    //Text shown over the grid when the level goes up, and the frames it has left.
+    banner: Option<(String, u32)>,
+}
+
+impl EffectLayer {
+    pub fn new(config: &Effects) -> EffectLayer {
+        EffectLayer {
+            config: *config,
+            particles: Vec::new(),
+            shake_frames: 0,
+            shake_strength: 0.0,
+            offset: (0.0, 0.0),
+            banner: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: &Effects) {
+        self.config = *config;
+        self.reset();
+    }
+
+    //Stops every running effect, for a new game.
+    pub fn reset(&mut self) {
+        self.particles.clear();
+        self.shake_frames = 0;
+        self.offset = (0.0, 0.0);
+        self.banner = None;
+    }
+
+    fn on(&self, effect: bool) -> bool {
+        self.config.enabled && effect
+    }
+
+    //Starts the effects of what happened on the last frame of the game.
+    pub fn start(&mut self, engine: &Engine, block_size: f32) {
+        for event in engine.events() {
+            match event {
+                GameEvent::LineClear(LineClears::Rustris)
+                | GameEvent::LineClear(LineClears::More(_))
+                | GameEvent::TSpin(_) => {
+                    if self.on(self.config.particles) {
+                        self.burst(engine, block_size);
+                    }
+                    self.shake(block_size / 4.0);
+                }
+                GameEvent::HardDrop => self.shake(block_size / 12.0),
+                GameEvent::LevelUp if self.on(self.config.level_up) => {
+                    let text = match engine.mode {
+                        GameMode::Marathon | GameMode::Drill => {
+                            format!("Level {}", engine.get_level())
+                        }
+                        GameMode::Master => format!("Level {:03}", engine.master.level),
+                    };
+                    self.banner = Some((text, BANNER_FRAMES));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn shake(&mut self, strength: f32) {
+        //A weaker shake doesn't cut a stronger one short.
+        if self.on(self.config.screen_shake)
+            && (self.shake_frames == 0 || strength >= self.shake_strength)
+        {
+            self.shake_frames = SHAKE_FRAMES;
+            self.shake_strength = strength;
+        }
+    }
+
+    //Sends sparks flying from the blocks that just locked, or from the middle of the grid if
+    //they were cleared right away.
+    fn burst(&mut self, engine: &Engine, block_size: f32) {
+        let mut sources = engine
+            .grid
+            .blocks
+            .iter()
+            .flat_map(|column| column.iter())
+            .filter(|block| block.state == BlockState::Filled && block.locked_at == engine.frame)
+            .map(|block| (block.position.x, block.position.y, Color::from(block.color)))
+            .collect::<Vec<_>>();
+        if sources.is_empty() {
+            sources.push((
+                engine.grid.width as f32 / 2.0,
+                engine.grid.height as f32 - 2.0,
+                Color::new(1.0, 1.0, 1.0, 1.0),
+            ));
+        }
+
+        for (x, y, color) in sources {
+            for _ in 0..PARTICLES_PER_BLOCK {
+                self.particles.push(Particle {
+                    x: (x + 0.5) * block_size,
+                    y: (y + 0.5) * block_size,
+                    speed_x: (rand::random::<f32>() - 0.5) * 8.0,
+                    speed_y: -rand::random::<f32>() * 6.0 - 1.0,
+                    frames: PARTICLE_FRAMES,
+                    color,
+                });
+            }
+        }
+    }
+
+    //Moves the effects on by a frame.
+    pub fn update(&mut self) {
+        self.particles.iter_mut().for_each(|particle| {
+            particle.x += particle.speed_x;
+            particle.y += particle.speed_y;
+            particle.speed_y += PARTICLE_GRAVITY;
+            particle.frames -= 1;
+        });
+        self.particles.retain(|particle| particle.frames > 0);
+
+        self.offset = if self.shake_frames > 0 {
+            self.shake_frames -= 1;
+            let strength = self.shake_strength * self.shake_frames as f32 / SHAKE_FRAMES as f32;
+            (
+                (rand::random::<f32>() - 0.5) * 2.0 * strength,
+                (rand::random::<f32>() - 0.5) * 2.0 * strength,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        if let Some((_, frames)) = self.banner.as_mut() {
+            *frames -= 1;
+            if *frames == 0 {
+                self.banner = None;
+            }
+        }
+    }
+
+    //How far the grid is pushed by the screen shake, in pixels.
+    pub fn offset(&self) -> (f32, f32) {
+        self.offset
+    }
+
+    //Rows that are collapsing during the line clear delay. They are drawn here instead of with the
+    //rest of the grid.
+    pub fn clearing_rows(&self, engine: &Engine) -> Vec<u32> {
+        match engine.phase {
+            Phase::LineClear(_) if self.on(self.config.line_clear) => {
+                engine.grid.find_line_clears()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    //Draws the effects over the grid, whose top left corner is at `origin`.
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        engine: &Engine,
+        style: &Style,
+        origin: Point2<f32>,
+        block_size: f32,
+    ) -> GameResult<()> {
+        let mut mesh = graphics::MeshBuilder::new();
+        let mut empty = true;
+        let hidden_rows = engine.options.hidden_rows as f32;
+
+        //Rows flash and shrink towards their middle as the line clear delay runs out.
+        if let Phase::LineClear(frames) = engine.phase {
+            let progress = frames as f32 / engine.line_clear_delay().max(1) as f32;
+            let alpha = if (frames / 4) % 2 == 0 { 0.9 } else { 0.5 };
+            for row in self.clearing_rows(engine) {
+                let height = block_size * progress;
+                mesh.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(
+                        0.0,
+                        row as f32 * block_size + (block_size - height) / 2.0,
+                        engine.grid.width as f32 * block_size,
+                        height,
+                    ),
+                    Color::new(1.0, 1.0, 1.0, alpha),
+                );
+                empty = false;
+            }
+        }
+
+        if self.on(self.config.lock_flash) {
+            engine
+                .grid
+                .blocks
+                .iter()
+                .flat_map(|column| column.iter())
+                .filter(|block| {
+                    block.state == BlockState::Filled
+                        && block.locked_at > 0
+                        && engine.frame - block.locked_at < LOCK_FLASH_FRAMES
+                        && block.position.y >= hidden_rows
+                })
+                .for_each(|block| {
+                    let age = (engine.frame - block.locked_at) as f32;
+                    mesh.rectangle(
+                        DrawMode::fill(),
+                        Rect::new(
+                            block.position.x * block_size,
+                            block.position.y * block_size,
+                            block_size,
+                            block_size,
+                        ),
+                        Color::new(1.0, 1.0, 1.0, 0.6 * (1.0 - age / LOCK_FLASH_FRAMES as f32)),
+                    );
+                    empty = false;
+                });
+        }
+
+        for particle in self.particles.iter() {
+            let alpha = particle.frames as f32 / PARTICLE_FRAMES as f32;
+            mesh.rectangle(
+                DrawMode::fill(),
+                Rect::new(particle.x, particle.y, block_size / 4.0, block_size / 4.0),
+                Color::new(particle.color.r, particle.color.g, particle.color.b, alpha),
+            );
+            empty = false;
+        }
+
+        if !empty {
+            let mesh = mesh.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default().dest(origin))?;
+        }
+
+        if let Some((text, frames)) = self.banner.as_ref() {
+            let alpha = (*frames as f32 / 30.0).min(1.0);
+            let text = graphics::Text::new(TextFragment::new(text.as_str()).font(style.font()));
+            let color = style.theme.text;
+            text.draw(
+                ctx,
+                DrawParam::new()
+                    .dest(Point2::new(
+                        origin.x + block_size,
+                        origin.y + (hidden_rows + engine.options.height as f32 / 3.0) * block_size,
+                    ))
+                    .color(Color::new(color.r, color.g, color.b, alpha)),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1461 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::ai::Board;
+use crate::finesse::*;
+use crate::garbage::Garbage;
+use crate::master::*;
+use crate::pathfinder::paths;
+use crate::pieces::PieceSet;
+use crate::random::Random;
+use crate::types::*;
+
+pub const MIN_GRID_WIDTH: u32 = 4;
+pub const MAX_GRID_WIDTH: u32 = 20;
+pub const MIN_GRID_HEIGHT: u32 = 4;
+pub const MAX_GRID_HEIGHT: u32 = 30;
+pub const MAX_HIDDEN_ROWS: u32 = 10;
+//Longest any of the delays in the options may be, in frames.
+pub const MAX_DELAY: u32 = 2 * FRAMES_PER_SECOND;
+pub const MAX_SDF: u32 = 100;
+
+//Struct to keep track of where our tetromino is going. Up rotates it clockwise.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MoveDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+    RotateCcw,
+    Rotate180,
+}
+
+//Bits of the inputs a player can make on a frame.
+pub const INPUT_LEFT: u8 = 1;
+pub const INPUT_RIGHT: u8 = 1 << 1;
+pub const INPUT_DOWN: u8 = 1 << 2;
+pub const INPUT_ROTATE_CW: u8 = 1 << 3;
+pub const INPUT_ROTATE_CCW: u8 = 1 << 4;
+pub const INPUT_HOLD: u8 = 1 << 5;
+pub const INPUT_HARD_DROP: u8 = 1 << 6;
+pub const INPUT_ROTATE_180: u8 = 1 << 7;
+
+//Moves each input bit makes, in the order they are applied within a frame.
+const INPUT_MOVES: [(u8, MoveDirection); 6] = [
+    (INPUT_ROTATE_CW, MoveDirection::Up),
+    (INPUT_ROTATE_CCW, MoveDirection::RotateCcw),
+    (INPUT_ROTATE_180, MoveDirection::Rotate180),
+    (INPUT_LEFT, MoveDirection::Left),
+    (INPUT_RIGHT, MoveDirection::Right),
+    (INPUT_DOWN, MoveDirection::Down),
+];
+
+//Everything the player did on a single frame. The game only changes through these, so a game can
+//be played again from its seed and the inputs of every frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameInput {
+    //Inputs pressed on this frame.
+    pub pressed: u8,
+    //Inputs held down on this frame. Held rotations and holds are applied to a tetromino as it
+    //spawns, so it can be rotated or held before it appears (IRS/IHS).
+    pub held: u8,
+}
+
+impl FrameInput {
+    pub fn press(&mut self, input: u8) {
+        self.pressed |= input;
+    }
+
+    pub fn is_pressed(&self, input: u8) -> bool {
+        self.pressed & input != 0
+    }
+
+    pub fn is_held(&self, input: u8) -> bool {
+        self.held & input != 0
+    }
+
+    fn held_rotation(&self) -> Option<MoveDirection> {
+        match (
+            self.is_held(INPUT_ROTATE_CW),
+            self.is_held(INPUT_ROTATE_CCW),
+            self.is_held(INPUT_ROTATE_180),
+        ) {
+            (true, false, false) => Some(MoveDirection::Up),
+            (false, true, false) => Some(MoveDirection::RotateCcw),
+            (false, false, true) => Some(MoveDirection::Rotate180),
+            _ => None,
+        }
+    }
+}
+
+//The rules the current game is played by.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    Marathon,
+    Master,
+    //Practice for finesse: every tetromino is given a place to go on an empty grid.
+    Drill,
+}
+
+//What the game is doing on the current frame. The counters hold the frames left in the phase.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Phase {
+    //A tetromino is in play.
+    Falling,
+    //Filled lines stay on the grid for a while before they are removed.
+    LineClear(u32),
+    //Entry delay (ARE) before the next tetromino spawns.
+    Are(u32),
+    GameOver,
+}
+
+//Why a game ended.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameEnd {
+    //A tetromino spawned, or came out of hold, on top of the stack.
+    BlockOut,
+    //A tetromino locked entirely above the visible rows.
+    LockOut,
+    //The last level of master mode was reached, or the last tetromino of a drill was placed.
+    Completed,
+    //Garbage from the opponent pushed blocks out the top of the grid.
+    TopOut,
+}
+
+//How locked blocks are shown while the game is played. Hidden blocks show up again once the
+//game is over.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum StackVisibility {
+    Visible,
+    //Blocks fade away this many frames after they lock.
+    Fading { frames: u32 },
+    //Only the outlines of the blocks are shown.
+    Outline,
+    Invisible,
+}
+
+impl StackVisibility {
+    //Cycles through the visibilities, for picking one in a menu.
+    pub fn next(&self) -> StackVisibility {
+        match self {
+            StackVisibility::Visible => StackVisibility::Fading {
+                frames: 5 * FRAMES_PER_SECOND,
+            },
+            StackVisibility::Fading { .. } => StackVisibility::Outline,
+            StackVisibility::Outline => StackVisibility::Invisible,
+            StackVisibility::Invisible => StackVisibility::Visible,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StackVisibility::Visible => "visible",
+            StackVisibility::Fading { .. } => "fading",
+            StackVisibility::Outline => "outline",
+            StackVisibility::Invisible => "invisible",
+        }
+    }
+}
+
+//How a locked block should be drawn on the current frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockLook {
+    //Filled in, with the given opacity.
+    Solid(f32),
+    Outline,
+    Hidden,
+}
+
+//Frames a fading block takes to disappear once it starts fading.
+const FADE_FRAMES: u32 = 30;
+
+//Options a game is started with.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameOptions {
+    //Columns of the grid.
+    pub width: u32,
+    //Visible rows of the grid.
+    pub height: u32,
+    //Rows above the visible ones. Tetrominoes spawn partly in them and may move through them,
+    //but they are never drawn.
+    pub hidden_rows: u32,
+    //Frames between a tetromino locking and the next one spawning.
+    pub are: u32,
+    //Frames filled lines stay on the grid before they are removed.
+    pub line_clear_delay: u32,
+    //Big mode: every cell of a piece is 2x2 blocks and pieces move sideways 2 blocks at a time.
+    pub big: bool,
+    //Frames Left or Right has to be held before the tetromino starts moving on its own (DAS).
+    pub das: u32,
+    //Frames between moves once it does (ARR). With 0 it moves all the way at once.
+    pub arr: u32,
+    //How many times faster than gravity the tetromino falls while Down is held (SDF). Soft
+    //dropping always moves it at least a row per frame.
+    pub sdf: u32,
+    //Last, as TOML files have to list tables after plain values.
+    pub stack: StackVisibility,
+}
+
+impl Default for GameOptions {
+    fn default() -> GameOptions {
+        GameOptions {
+            width: 10,
+            height: 20,
+            hidden_rows: 2,
+            are: 6,
+            line_clear_delay: 20,
+            big: false,
+            stack: StackVisibility::Visible,
+            das: 10,
+            arr: 2,
+            sdf: 20,
+        }
+    }
+}
+
+impl GameOptions {
+    //Checks that a game can be played with these options.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width < MIN_GRID_WIDTH || self.width > MAX_GRID_WIDTH {
+            return Err(format!(
+                "grid width must be between {} and {}, got {}",
+                MIN_GRID_WIDTH, MAX_GRID_WIDTH, self.width
+            ));
+        }
+        if self.height < MIN_GRID_HEIGHT || self.height > MAX_GRID_HEIGHT {
+            return Err(format!(
+                "grid height must be between {} and {}, got {}",
+                MIN_GRID_HEIGHT, MAX_GRID_HEIGHT, self.height
+            ));
+        }
+        if self.hidden_rows > MAX_HIDDEN_ROWS {
+            return Err(format!(
+                "there can be at most {} hidden rows, got {}",
+                MAX_HIDDEN_ROWS, self.hidden_rows
+            ));
+        }
+        if self.hidden_rows < self.scale() {
+            return Err(format!(
+                "there must be at least {} hidden rows",
+                self.scale()
+            ));
+        }
+        if self.big && (!self.width.is_multiple_of(2) || self.width < MIN_GRID_WIDTH * 2) {
+            return Err(format!(
+                "big mode needs an even grid width of at least {}, got {}",
+                MIN_GRID_WIDTH * 2,
+                self.width
+            ));
+        }
+        for (name, delay) in [
+            ("are", self.are),
+            ("line_clear_delay", self.line_clear_delay),
+            ("das", self.das),
+            ("arr", self.arr),
+        ]
+        .iter()
+        {
+            if *delay > MAX_DELAY {
+                return Err(format!(
+                    "{} must be at most {} frames, got {}",
+                    name, MAX_DELAY, delay
+                ));
+            }
+        }
+        if self.sdf < 1 || self.sdf > MAX_SDF {
+            return Err(format!(
+                "sdf must be between 1 and {}, got {}",
+                MAX_SDF, self.sdf
+            ));
+        }
+        Ok(())
+    }
+
+    //Checks that every piece of the set fits on an empty grid where it spawns, so games with it
+    //don't end on the first tetromino.
+    pub fn validate_pieces(&self, pieces: &PieceSet) -> Result<(), String> {
+        let width = self.width as f32;
+        let height = (self.height + self.hidden_rows) as f32;
+        for index in 0..pieces.len() {
+            let tetromino = Tetromino::spawn(index, self.spawn_position(), self.scale(), pieces);
+            let fits = tetromino.blocks(pieces).iter().all(|block| {
+                let x = tetromino.position.x + block.position.x;
+                let y = tetromino.position.y + block.position.y;
+                x >= 0.0 && x < width && y >= 0.0 && y < height
+            });
+            if !fits {
+                return Err(format!(
+                    "piece {} of {} doesn't fit on a {}x{} grid with {} hidden rows{}",
+                    pieces.pieces[index].name,
+                    pieces.name,
+                    self.width,
+                    self.height,
+                    self.hidden_rows,
+                    if self.big { " in big mode" } else { "" }
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    //Tetrominoes spawn centered on the top visible row, sticking out into the hidden rows. In big
+    //mode they stay on even columns, so they line up with the walls.
+    pub fn spawn_position(&self) -> Point2<f32> {
+        let scale = self.scale();
+        Point2::new(
+            (self.width / 2 / scale * scale) as f32,
+            self.hidden_rows as f32,
+        )
+    }
+
+    //Blocks every cell of a piece takes up in either direction.
+    pub fn scale(&self) -> u32 {
+        if self.big {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+//Something that happened in the game, for the sounds and effects that go with it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    Move,
+    Rotate,
+    Lock,
+    HardDrop,
+    Hold,
+    LineClear(LineClears),
+    //A T piece locked after a rotation, with three of the corners around its center filled.
+    TSpin(LineClears),
+    //Rows of garbage sent to the opponent.
+    Attack(u32),
+    LevelUp,
+    GameOver,
+}
+
+//Struct to hold the state of a single game, independent of how it is drawn or controlled.
+#[derive(Serialize, Deserialize)]
+pub struct Engine {
+    pub grid: Grid,
+    pub tetromino: Option<Tetromino>,
+    pub tetromino_next: Option<Tetromino>,
+    pub tetromino_hold: Option<Tetromino>,
+    //Saved games keep the name of their piece set instead, and the set is put back when they are
+    //loaded.
+    #[serde(skip, default = "standard_pieces")]
+    pub pieces: Arc<PieceSet>,
+    pub mode: GameMode,
+    pub options: GameOptions,
+    pub master: Master,
+    #[serde(default)]
+    pub finesse: Finesse,
+    #[serde(default)]
+    pub garbage: Garbage,
+    pub phase: Phase,
+    pub frame: u32,
+    //Inputs of the frame being played.
+    frame_input: FrameInput,
+    random: Random,
+    gravity_counter: u32,
+    move_tetromino_every: u32,
+    to_lock: bool,
+    lock_timer: u32,
+    soft_drop: u32,
+    hold_used: bool,
+    buffered_rotation: Option<MoveDirection>,
+    buffered_hold: bool,
+    //Direction held down for auto shift, and for how many frames.
+    shift_direction: Option<MoveDirection>,
+    shift_frames: u32,
+    //Whether the last move of the tetromino was a rotation, for spotting T-spins.
+    #[serde(default)]
+    last_rotated: bool,
+    //What happened on the last frame.
+    #[serde(skip)]
+    events: Vec<GameEvent>,
+    #[serde(default)]
+    pub end: Option<GameEnd>,
+    //Tetrominoes locked so far.
+    #[serde(default)]
+    pub pieces_locked: u32,
+    pub score: u64,
+    pub single: u32,
+    pub double: u32,
+    pub tripple: u32,
+    pub rustris: u32,
+    //Lines cleared five or more at a time.
+    #[serde(default)]
+    pub more_lines: u32,
+}
+
+fn standard_pieces() -> Arc<PieceSet> {
+    Arc::new(PieceSet::standard())
+}
+
+//Where a move takes a tetromino, if it fits anywhere according to `collides`. Rotations try each
+//offset of the piece's kick table until one fits.
+pub fn moved_tetromino<F: Fn(&Tetromino) -> bool>(
+    tetromino: &Tetromino,
+    direction: &MoveDirection,
+    pieces: &PieceSet,
+    collides: F,
+) -> Option<Tetromino> {
+    let mut moved = *tetromino;
+    let scale = tetromino.scale as f32;
+
+    let kicks = match direction {
+        MoveDirection::Left => {
+            moved.position.x -= scale;
+            vec![(0, 0)]
+        }
+        MoveDirection::Right => {
+            moved.position.x += scale;
+            vec![(0, 0)]
+        }
+        MoveDirection::Down => {
+            moved.position.y += 1.0;
+            vec![(0, 0)]
+        }
+        MoveDirection::Up => {
+            moved.rotation.rotate_cw();
+            pieces.pieces[tetromino.piece].kicks.cw[tetromino.rotation.index()].clone()
+        }
+        MoveDirection::RotateCcw => {
+            moved.rotation.rotate_ccw();
+            pieces.pieces[tetromino.piece].kicks.ccw[tetromino.rotation.index()].clone()
+        }
+        //Pieces have no kicks for half turns, so they only turn where they are.
+        MoveDirection::Rotate180 => {
+            moved.rotation.rotate_cw();
+            moved.rotation.rotate_cw();
+            vec![(0, 0)]
+        }
+    };
+
+    kicks
+        .iter()
+        .map(|(x, y)| {
+            let mut kicked = moved;
+            kicked.position.x += *x as f32 * scale;
+            kicked.position.y -= *y as f32 * scale;
+            kicked
+        })
+        .find(|kicked| !collides(kicked))
+}
+
+impl Engine {
+    pub fn new(mode: GameMode, options: GameOptions, pieces: Arc<PieceSet>, seed: u64) -> Engine {
+        Engine {
+            grid: Grid::new(
+                options.width,
+                options.height + options.hidden_rows,
+                Color::new(0.5, 0.5, 0.5, 1.0),
+            ),
+            tetromino: None,
+            tetromino_next: None,
+            tetromino_hold: None,
+            pieces,
+            mode,
+            options,
+            master: Master::new(),
+            finesse: Finesse::new(),
+            garbage: Garbage::new(seed),
+            phase: Phase::Are(0),
+            frame: 0,
+            frame_input: FrameInput::default(),
+            random: Random::new(seed),
+            gravity_counter: 0,
+            move_tetromino_every: 500,
+            to_lock: false,
+            lock_timer: 0,
+            soft_drop: 0,
+            hold_used: false,
+            buffered_rotation: None,
+            buffered_hold: false,
+            shift_direction: None,
+            shift_frames: 0,
+            last_rotated: false,
+            events: Vec::new(),
+            end: None,
+            pieces_locked: 0,
+            score: 0,
+            single: 0,
+            double: 0,
+            tripple: 0,
+            rustris: 0,
+            more_lines: 0,
+        }
+    }
+
+    //Starts the game by bringing in the first tetromino.
+    pub fn start(&mut self) {
+        self.grid.reset();
+        self.spawn_tetromino();
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    //A one line summary of how the game went.
+    pub fn summary(&self) -> String {
+        match self.mode {
+            GameMode::Marathon => format!("Game over! Score: {}", self.score),
+            GameMode::Master => format!(
+                "Game over! Grade: {}  Level: {}  Time: {}",
+                self.master.grade(),
+                self.master.level,
+                format_time(self.frame)
+            ),
+            GameMode::Drill => format!(
+                "Drill over! Targets hit: {} / {}  Finesse faults: {}  Time: {}",
+                self.finesse.targets_hit,
+                self.pieces_locked,
+                self.finesse.faults,
+                format_time(self.frame)
+            ),
+        }
+    }
+
+    //Master mode has timings of its own and 20G, so finesse is only kept track of in the others.
+    fn tracks_finesse(&self) -> bool {
+        self.mode != GameMode::Master
+    }
+
+    //Gives the tetromino in play a random place to go, in drills.
+    fn set_drill_target(&mut self) {
+        if self.mode != GameMode::Drill {
+            return;
+        }
+        let tetromino = match self.tetromino {
+            Some(tetromino) => tetromino,
+            None => return,
+        };
+        let targets = paths(&Board::from_grid(&self.grid), &self.pieces, &tetromino);
+        if !targets.is_empty() {
+            self.finesse.target = Some(targets[self.random.below(targets.len())].tetromino);
+        }
+    }
+
+    //Brings the next tetromino into the grid, applying any rotation or hold that was buffered
+    //during the entry delay or is being held down. The game is over if there is no room for it.
+    pub fn spawn_tetromino(&mut self) {
+        //Drills start every tetromino on an empty grid.
+        if self.mode == GameMode::Drill {
+            self.grid.reset();
+        }
+        self.generate_tetromino();
+        self.phase = Phase::Falling;
+        self.last_rotated = false;
+        self.soft_drop = 0;
+        self.gravity_counter = 0;
+        self.to_lock = false;
+        self.lock_timer = 0;
+        self.hold_used = false;
+
+        let hold = self.buffered_hold || self.frame_input.is_held(INPUT_HOLD);
+        self.buffered_hold = false;
+        if hold {
+            self.swap_hold();
+        }
+
+        if self.collides(self.tetromino.as_ref().unwrap()) {
+            self.finish(GameEnd::BlockOut);
+            return;
+        }
+        if !hold {
+            self.finesse.on_spawn(self.tetromino.as_ref().unwrap());
+            self.set_drill_target();
+        }
+
+        let rotation = self
+            .buffered_rotation
+            .take()
+            .or_else(|| self.frame_input.held_rotation());
+        if let Some(direction) = rotation {
+            if let Some(tetromino) = self.validate_move(&direction) {
+                self.tetromino = Some(tetromino);
+            }
+        }
+
+        if self.mode == GameMode::Master {
+            self.master.on_spawn();
+        }
+        if self.gravity() >= GRAVITY_20G {
+            self.drop_tetromino();
+        }
+    }
+
+    //Generates a random tetromino.
+    pub fn generate_tetromino(&mut self) {
+        if self.tetromino_next.is_none() {
+            self.tetromino_next = Some(self.random_tetromino());
+        }
+        self.tetromino = self.tetromino_next.take();
+        self.tetromino_next = Some(self.random_tetromino());
+    }
+
+    fn random_tetromino(&mut self) -> Tetromino {
+        Tetromino::random(
+            self.options.spawn_position(),
+            self.options.scale(),
+            &self.pieces,
+            &mut self.random,
+        )
+    }
+
+    //Puts the current tetromino on hold and brings out the held one, or the next one if nothing
+    //was held yet.
+    fn swap_hold(&mut self) {
+        let current = self.tetromino.take().unwrap();
+        let held = Tetromino::spawn(
+            current.piece,
+            self.options.spawn_position(),
+            self.options.scale(),
+            &self.pieces,
+        );
+
+        match self.tetromino_hold.replace(held) {
+            Some(tetromino) => self.tetromino = Some(tetromino),
+            None => self.generate_tetromino(),
+        }
+
+        self.hold_used = true;
+        self.last_rotated = false;
+        self.events.push(GameEvent::Hold);
+        self.finesse.on_hold(self.tetromino.as_ref().unwrap());
+        self.set_drill_target();
+        self.soft_drop = 0;
+        self.gravity_counter = 0;
+        self.to_lock = false;
+        self.lock_timer = 0;
+    }
+
+    //Lines cleared so far.
+    pub fn lines(&self) -> u32 {
+        self.single + (self.double * 2) + (self.tripple * 3) + (self.rustris * 4) + self.more_lines
+    }
+
+    pub fn get_level(&self) -> u32 {
+        let sum_lines = self.lines();
+        if sum_lines != 0 {
+            (sum_lines as f32 / 10.0).ceil() as u32
+        } else {
+            1
+        }
+    }
+
+    //How far the game has got, counting from 0: the level in marathon and the section in master
+    //mode.
+    pub fn stage(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => self.get_level() - 1,
+            GameMode::Master => self.master.level / 100,
+        }
+    }
+
+    pub fn get_move_delay(&self) -> u32 {
+        (self.move_tetromino_every as f32 / (self.get_level() as f32 / 2.0)) as u32
+    }
+
+    //Rows per frame the tetromino falls, in 1/256ths of a row.
+    pub fn gravity(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => {
+                (GRAVITY_1G * 1000 / (self.get_move_delay().max(1) * FRAMES_PER_SECOND)).max(1)
+            }
+            GameMode::Master => self.master.gravity(),
+        }
+    }
+
+    //Frames a direction has to be held before auto shift kicks in.
+    pub fn das(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => self.options.das,
+            GameMode::Master => self.master.timing().das,
+        }
+    }
+
+    //Frames between auto shift moves.
+    pub fn arr(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => self.options.arr,
+            GameMode::Master => 1,
+        }
+    }
+
+    //Frames a tetromino can rest on the stack before it locks.
+    pub fn lock_delay(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => {
+                self.get_move_delay() * FRAMES_PER_SECOND / 1000
+            }
+            GameMode::Master => self.master.timing().lock_delay,
+        }
+    }
+
+    //Entry delay after a tetromino locks, depending on whether it cleared any lines.
+    pub fn are(&self, cleared: bool) -> u32 {
+        match (self.mode, cleared) {
+            (GameMode::Marathon, _) | (GameMode::Drill, _) => self.options.are,
+            (GameMode::Master, false) => self.master.timing().are,
+            (GameMode::Master, true) => self.master.timing().line_are,
+        }
+    }
+
+    pub fn line_clear_delay(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon | GameMode::Drill => self.options.line_clear_delay,
+            GameMode::Master => self.master.timing().line_clear,
+        }
+    }
+
+    //How a locked block looks right now. The whole stack is shown once the game is over.
+    pub fn block_look(&self, block: &Block) -> BlockLook {
+        if self.phase == Phase::GameOver {
+            return BlockLook::Solid(1.0);
+        }
+        match self.options.stack {
+            StackVisibility::Visible => BlockLook::Solid(1.0),
+            StackVisibility::Fading { frames } => {
+                let age = self.frame.saturating_sub(block.locked_at);
+                if age < frames {
+                    BlockLook::Solid(1.0)
+                } else if age < frames + FADE_FRAMES {
+                    BlockLook::Solid(1.0 - (age - frames) as f32 / FADE_FRAMES as f32)
+                } else {
+                    BlockLook::Hidden
+                }
+            }
+            StackVisibility::Outline => BlockLook::Outline,
+            StackVisibility::Invisible => BlockLook::Hidden,
+        }
+    }
+
+    //Checks if the tetromino overlaps with filled blocks or the edges of the grid.
+    pub fn collides(&self, tetromino: &Tetromino) -> bool {
+        tetromino.blocks(&self.pieces).iter().any(|block| {
+            let x = tetromino.position.x + block.position.x;
+            let y = tetromino.position.y + block.position.y;
+            x < 0.0 || y < 0.0 || self.grid.check_occupied(x as u32, y as u32)
+        })
+    }
+
+    //Checks for a T-spin: a T piece that was rotated into place, with at least three of the four
+    //corners around its center blocked.
+    fn is_t_spin(&self, tetromino: &Tetromino) -> bool {
+        if !self.last_rotated || self.pieces.pieces[tetromino.piece].name != "T" {
+            return false;
+        }
+        let x = tetromino.position.x as i32;
+        let y = tetromino.position.y as i32;
+        let scale = tetromino.scale as i32;
+        [
+            (x - 1, y - 1),
+            (x + scale, y - 1),
+            (x - 1, y + scale),
+            (x + scale, y + scale),
+        ]
+        .iter()
+        .filter(|(x, y)| *x < 0 || *y < 0 || self.grid.check_occupied(*x as u32, *y as u32))
+        .count()
+            >= 3
+    }
+
+    //Checks if every block of the tetromino is inside the hidden rows.
+    fn above_visible_rows(&self, tetromino: &Tetromino) -> bool {
+        tetromino
+            .blocks(&self.pieces)
+            .iter()
+            .all(|block| tetromino.position.y + block.position.y < self.options.hidden_rows as f32)
+    }
+
+    //Validates if the incoming move is a proper one. If it is it updates our tetromino with its
+    //new values.
+    pub fn validate_move(&mut self, direction: &MoveDirection) -> Option<Tetromino> {
+        let tetromino = self.tetromino?;
+        let next = moved_tetromino(&tetromino, direction, &self.pieces, |tetromino| {
+            self.collides(tetromino)
+        });
+
+        //Master mode only gives the tetromino a new lock delay when it can fall further.
+        let resets_lock = match direction {
+            MoveDirection::Down => true,
+            _ => self.mode != GameMode::Master,
+        };
+
+        if next.is_some() && self.to_lock && resets_lock {
+            self.to_lock = false;
+            self.lock_timer = 0;
+        }
+
+        next
+    }
+
+    //Moves our tetromino to the its new position. Returns whether the tetromino moved.
+    pub fn move_tetromino(&mut self, direction: &MoveDirection) -> bool {
+        //Check if incoming move is valid. If yes, swap current tetromino with new one. If not,
+        //keep current tetromino
+        let next = self.validate_move(direction);
+        let moved = next.is_some();
+        self.tetromino = next.or(self.tetromino);
+        if moved {
+            self.last_rotated = match direction {
+                MoveDirection::Left | MoveDirection::Right => {
+                    self.events.push(GameEvent::Move);
+                    false
+                }
+                MoveDirection::Down => false,
+                MoveDirection::Up | MoveDirection::RotateCcw | MoveDirection::Rotate180 => {
+                    self.events.push(GameEvent::Rotate);
+                    true
+                }
+            };
+        }
+
+        //Check if tetromino reached the lowest point of our grid. If yes, lock it up and generate
+        //a new one.
+        if self.validate_move(&MoveDirection::Down).is_none() && !self.to_lock {
+            self.to_lock = true;
+        }
+
+        moved
+    }
+
+    //Drops the tetromino as far down as it goes. Under 20G this keeps it on the stack.
+    pub fn drop_tetromino(&mut self) {
+        while self.move_tetromino(&MoveDirection::Down) {}
+    }
+
+    //Drops the tetromino and locks it right away.
+    pub fn hard_drop(&mut self) {
+        if self.phase == Phase::Falling {
+            self.drop_tetromino();
+            self.events.push(GameEvent::HardDrop);
+            self.lock_and_score();
+        }
+    }
+
+    //Handles a player move. Rotations and holds made while no tetromino is in play are kept and
+    //applied when the next one spawns.
+    pub fn input(&mut self, direction: &MoveDirection) {
+        if self.phase != Phase::Falling {
+            if let MoveDirection::Up | MoveDirection::RotateCcw | MoveDirection::Rotate180 =
+                direction
+            {
+                self.buffered_rotation = Some(*direction);
+            }
+            return;
+        }
+
+        //Todo Make this more ... Professional?
+        if self.mode != GameMode::Master {
+            self.lock_timer = 0;
+        }
+
+        if self.move_tetromino(direction) {
+            if let MoveDirection::Down = direction {
+                self.soft_drop += 1;
+            }
+        }
+
+        if self.gravity() >= GRAVITY_20G {
+            self.drop_tetromino();
+        }
+    }
+
+    //Puts the current tetromino on hold. Only one hold is allowed per tetromino.
+    pub fn hold(&mut self) {
+        match self.phase {
+            Phase::Falling => {
+                if !self.hold_used {
+                    self.swap_hold();
+                    if self.collides(self.tetromino.as_ref().unwrap()) {
+                        self.finish(GameEnd::BlockOut);
+                    } else if self.gravity() >= GRAVITY_20G {
+                        self.drop_tetromino();
+                    }
+                }
+            }
+            Phase::LineClear(_) | Phase::Are(_) => self.buffered_hold = true,
+            Phase::GameOver => {}
+        }
+    }
+
+    //Whether the tetromino in play can still be put on hold.
+    pub fn can_hold(&self) -> bool {
+        self.phase == Phase::Falling && !self.hold_used
+    }
+
+    //Pulls the tetromino down according to the current gravity.
+    //Soft dropping makes it fall faster while Down is held.
+    fn apply_gravity(&mut self) {
+        let soft = self.frame_input.is_held(INPUT_DOWN);
+        self.gravity_counter += if soft {
+            (self.gravity() * self.options.sdf).max(GRAVITY_1G)
+        } else {
+            self.gravity()
+        };
+        while self.gravity_counter >= GRAVITY_1G {
+            self.gravity_counter -= GRAVITY_1G;
+            if !self.move_tetromino(&MoveDirection::Down) {
+                self.gravity_counter = 0;
+            } else if soft {
+                self.soft_drop += 1;
+            }
+        }
+    }
+
+    //Moves the tetromino on its own while Left or Right is held down (DAS and ARR). The charge
+    //carries over from one tetromino to the next.
+    fn auto_shift(&mut self) {
+        let direction = match (
+            self.frame_input.is_held(INPUT_LEFT),
+            self.frame_input.is_held(INPUT_RIGHT),
+        ) {
+            (true, false) => Some(MoveDirection::Left),
+            (false, true) => Some(MoveDirection::Right),
+            _ => None,
+        };
+        if direction != self.shift_direction {
+            self.shift_direction = direction;
+            self.shift_frames = 0;
+            return;
+        }
+        let direction = match direction {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        self.shift_frames = self.shift_frames.saturating_add(1);
+        let das = self.das();
+        if self.phase != Phase::Falling || self.shift_frames < das {
+            return;
+        }
+        match self.arr() {
+            0 => {
+                for _ in 0..self.options.width {
+                    self.input(&direction);
+                }
+            }
+            arr if (self.shift_frames - das).is_multiple_of(arr) => self.input(&direction),
+            _ => (),
+        }
+    }
+
+    //Waits `frames` before spawning the next tetromino, or spawns it right away.
+    fn enter_are(&mut self, frames: u32) {
+        if frames == 0 {
+            self.spawn_tetromino();
+        } else {
+            self.phase = Phase::Are(frames);
+        }
+    }
+
+    //Locks the tetromino, scores it and moves on to the line clear delay or the entry delay. The
+    //game is over if the tetromino locked entirely outside the visible rows.
+    fn lock_and_score(&mut self) {
+        self.to_lock = false;
+        self.lock_timer = 0;
+        let locked_out = self.above_visible_rows(self.tetromino.as_ref().unwrap());
+        let t_spin = self.is_t_spin(self.tetromino.as_ref().unwrap());
+        let stage = self.stage();
+        if self.tracks_finesse() {
+            let board = Board::from_grid(&self.grid);
+            self.finesse
+                .on_lock(&board, &self.pieces, self.tetromino.as_ref().unwrap());
+        }
+        self.lock_tetromino();
+        self.tetromino = None;
+        self.pieces_locked += 1;
+
+        let rows = self.grid.find_line_clears().len() as u32;
+        //Big blocks are two rows tall, so it takes two rows to make a line.
+        let lines = rows / self.options.scale();
+        if self.mode == GameMode::Master {
+            let bravo = rows > 0 && self.grid.filled_blocks() == rows * self.grid.width;
+            self.master
+                .on_lock(lines, self.soft_drop, bravo, self.frame);
+        }
+        let clears = LineClears::get_clear_type(lines);
+        self.update_score(&clears);
+        let attack = self.garbage.on_lock(lines, t_spin);
+
+        self.events.push(GameEvent::Lock);
+        if lines > 0 {
+            self.events.push(GameEvent::LineClear(clears));
+        }
+        if t_spin {
+            self.events.push(GameEvent::TSpin(clears));
+        }
+        if attack > 0 {
+            self.events.push(GameEvent::Attack(attack));
+        }
+        if self.stage() > stage {
+            self.events.push(GameEvent::LevelUp);
+        }
+
+        let drilled = self.mode == GameMode::Drill && self.pieces_locked >= DRILL_PIECES;
+        if self.master.is_finished() || drilled {
+            self.finish(GameEnd::Completed);
+        } else if locked_out {
+            self.finish(GameEnd::LockOut);
+        } else if lines > 0 {
+            if self.line_clear_delay() == 0 {
+                self.grid.clear_lines();
+                self.enter_are(self.are(true));
+            } else {
+                self.phase = Phase::LineClear(self.line_clear_delay());
+            }
+        } else if !self.raise_garbage() {
+            self.finish(GameEnd::TopOut);
+        } else {
+            self.enter_are(self.are(false));
+        }
+    }
+
+    //Garbage sent by the opponent, raised the next time a tetromino locks without clearing lines.
+    pub fn receive_garbage(&mut self, rows: u32) {
+        self.garbage
+            .receive(rows, self.options.width, self.options.scale());
+    }
+
+    //Raises the pending garbage under the stack. Returns false if it pushed blocks out the top.
+    fn raise_garbage(&mut self) -> bool {
+        let scale = self.options.scale();
+        let mut fits = true;
+        for (rows, hole) in self.garbage.take() {
+            for _ in 0..rows * scale {
+                fits &= self.grid.raise(hole..hole + scale, self.frame);
+            }
+        }
+        fits
+    }
+
+    fn finish(&mut self, end: GameEnd) {
+        self.phase = Phase::GameOver;
+        self.end = Some(end);
+        self.events.push(GameEvent::GameOver);
+    }
+
+    //What happened on the last frame that was played.
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    //Advances the game by a single frame, applying the inputs made on it first.
+    pub fn tick(&mut self, input: FrameInput) {
+        self.events.clear();
+        if self.phase == Phase::GameOver {
+            return;
+        }
+        self.frame += 1;
+
+        self.frame_input = input;
+        if input.is_pressed(INPUT_HOLD) {
+            self.hold();
+        }
+        if self.tracks_finesse() {
+            self.finesse.on_input(&input);
+        }
+        for (bit, direction) in INPUT_MOVES.iter() {
+            if input.is_pressed(*bit) {
+                self.input(direction);
+            }
+        }
+        self.auto_shift();
+        if input.is_pressed(INPUT_HARD_DROP) {
+            self.hard_drop();
+        }
+        if self.phase == Phase::GameOver {
+            return;
+        }
+
+        match self.phase {
+            Phase::Falling => {
+                if self.to_lock {
+                    self.lock_timer += 1;
+                }
+
+                if self.to_lock && self.lock_timer >= self.lock_delay() {
+                    self.lock_and_score();
+                } else {
+                    self.apply_gravity();
+                }
+            }
+            Phase::LineClear(frames) => {
+                if frames > 1 {
+                    self.phase = Phase::LineClear(frames - 1);
+                } else {
+                    //Clear filled lines now that the delay is over.
+                    self.grid.clear_lines();
+                    self.enter_are(self.are(true));
+                }
+            }
+            Phase::Are(frames) => {
+                if frames > 1 {
+                    self.phase = Phase::Are(frames - 1);
+                } else {
+                    self.spawn_tetromino();
+                }
+            }
+            Phase::GameOver => {}
+        }
+    }
+
+    //Locks tetromino on the grid.
+    pub fn lock_tetromino(&mut self) {
+        self.tetromino
+            .as_ref()
+            .unwrap()
+            .blocks(&self.pieces)
+            .iter()
+            .for_each(|block| {
+                let abs_block_x = self.tetromino.as_ref().unwrap().position.x + block.position.x;
+                let abs_block_y = self.tetromino.as_ref().unwrap().position.y + block.position.y;
+
+                self.grid.blocks[abs_block_x as usize][abs_block_y as usize] = Block {
+                    position: Point2::new(abs_block_x, abs_block_y),
+                    color: block.color,
+                    state: BlockState::Filled,
+                    locked_at: self.frame,
+                    piece: block.piece,
+                };
+            });
+    }
+
+    fn update_score(&mut self, clears: &LineClears) {
+        //Update the score
+        match clears {
+            LineClears::NoClear => {}
+            LineClears::Single => {
+                self.single += 1;
+                self.score += 1000;
+            }
+            LineClears::Double => {
+                self.double += 1;
+                self.score += 4000;
+            }
+            LineClears::Tripple => {
+                self.tripple += 1;
+                self.score += 6000;
+            }
+            LineClears::Rustris => {
+                self.rustris += 1;
+                self.score += 12000;
+            }
+            //Past a rustris, every line scores what a line of a rustris does.
+            LineClears::More(lines) => {
+                self.more_lines += lines;
+                self.score += 3000 * *lines as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{cells, dropped};
+    use std::path::Path;
+
+    //Drops the tetromino in play to the floor, fills the bottom `rows` rows around it and locks it.
+    fn lock_on_filled_rows(engine: &mut Engine, rows: u32) {
+        let board = Board::from_grid(&engine.grid);
+        let tetromino = dropped(&board, &engine.pieces, engine.tetromino.as_ref().unwrap());
+        let taken = cells(&engine.pieces, &tetromino);
+        let height = engine.grid.height;
+        for x in 0..engine.grid.width {
+            for y in height - rows..height {
+                if !taken.contains(&(x as i32, y as i32)) {
+                    engine.grid.blocks[x as usize][y as usize].state = BlockState::Filled;
+                }
+            }
+        }
+        engine.tetromino = Some(tetromino);
+        engine.lock_and_score();
+    }
+
+    fn pressed(input: u8) -> FrameInput {
+        FrameInput {
+            pressed: input,
+            held: input,
+        }
+    }
+
+    fn held(input: u8) -> FrameInput {
+        FrameInput {
+            pressed: 0,
+            held: input,
+        }
+    }
+
+    #[test]
+    fn big_single_is_one_line() {
+        let options = GameOptions {
+            big: true,
+            ..GameOptions::default()
+        };
+        let mut engine = Engine::new(GameMode::Marathon, options, standard_pieces(), 1);
+        engine.start();
+        lock_on_filled_rows(&mut engine, 2);
+        assert_eq!(engine.single, 1);
+        assert_eq!(engine.lines(), 1);
+        assert_eq!(engine.score, 1000);
+    }
+
+    #[test]
+    fn pentomino_clears_five_lines() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/pieces/pentominoes.toml");
+        let pieces = Arc::new(PieceSet::load(&path).unwrap());
+        let i = pieces
+            .pieces
+            .iter()
+            .position(|piece| piece.name == "I")
+            .unwrap();
+        let mut engine = Engine::new(
+            GameMode::Marathon,
+            GameOptions::default(),
+            pieces.clone(),
+            1,
+        );
+        engine.start();
+        let mut upright = Tetromino::spawn(i, engine.options.spawn_position(), 1, &pieces);
+        upright.rotation.rotate_cw();
+        engine.tetromino = Some(upright);
+        lock_on_filled_rows(&mut engine, 5);
+        assert_eq!(engine.lines(), 5);
+        assert_eq!(engine.score, 15000);
+        assert!(engine
+            .events()
+            .contains(&GameEvent::LineClear(LineClears::More(5))));
+    }
+
+    #[test]
+    fn grid_size_has_to_be_in_range() {
+        let with = |width, height, hidden_rows| GameOptions {
+            width,
+            height,
+            hidden_rows,
+            ..GameOptions::default()
+        };
+        assert_eq!(with(MIN_GRID_WIDTH, MIN_GRID_HEIGHT, 1).validate(), Ok(()));
+        assert_eq!(
+            with(MAX_GRID_WIDTH, MAX_GRID_HEIGHT, MAX_HIDDEN_ROWS).validate(),
+            Ok(())
+        );
+        assert!(with(MIN_GRID_WIDTH - 1, 20, 2).validate().is_err());
+        assert!(with(MAX_GRID_WIDTH + 1, 20, 2).validate().is_err());
+        assert!(with(10, MIN_GRID_HEIGHT - 1, 2).validate().is_err());
+        assert!(with(10, MAX_GRID_HEIGHT + 1, 2).validate().is_err());
+        assert!(with(10, 20, 0).validate().is_err());
+        assert!(with(10, 20, MAX_HIDDEN_ROWS + 1).validate().is_err());
+    }
+
+    #[test]
+    fn plays_on_a_small_grid() {
+        let options = GameOptions {
+            width: 6,
+            height: 8,
+            hidden_rows: 3,
+            ..GameOptions::default()
+        };
+        assert_eq!(options.validate(), Ok(()));
+        let mut engine = Engine::new(GameMode::Marathon, options, standard_pieces(), 1);
+        engine.start();
+        assert_eq!((engine.grid.width, engine.grid.height), (6, 11));
+        let tetromino = engine.tetromino.unwrap();
+        assert_eq!(tetromino.position, Point2::new(3.0, 3.0));
+
+        //Dropping every tetromino where it spawns stacks up to the top within a few pieces.
+        while engine.phase != Phase::GameOver && engine.frame < 10_000 {
+            engine.tick(pressed(INPUT_HARD_DROP));
+        }
+        assert!(matches!(
+            engine.end,
+            Some(GameEnd::BlockOut) | Some(GameEnd::LockOut)
+        ));
+        assert!(engine.pieces_locked < 20);
+    }
+
+    #[test]
+    fn pieces_have_to_fit_where_they_spawn() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/pieces/pentominoes.toml");
+        let pentominoes = PieceSet::load(&path).unwrap();
+        let standard = PieceSet::standard();
+        let narrow = GameOptions {
+            width: MIN_GRID_WIDTH,
+            ..GameOptions::default()
+        };
+        assert_eq!(narrow.validate_pieces(&standard), Ok(()));
+        //The I pentomino is five blocks wide.
+        assert!(narrow.validate_pieces(&pentominoes).is_err());
+        assert_eq!(GameOptions::default().validate_pieces(&pentominoes), Ok(()));
+
+        let big = GameOptions {
+            width: MIN_GRID_WIDTH * 2,
+            big: true,
+            ..GameOptions::default()
+        };
+        assert_eq!(big.validate_pieces(&standard), Ok(()));
+        assert!(big.validate_pieces(&pentominoes).is_err());
+
+        //A bar standing three cells above its center sticks out over two hidden rows.
+        let path = std::env::temp_dir().join(format!("rustris-tall-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "name = \"Tall\"\n[[pieces]]\nname = \"I\"\ncolor = [1, 2, 3]\nrotations = [[[0, -3], [0, -2], [0, -1], [0, 0]]]\n",
+        )
+        .unwrap();
+        let tall = PieceSet::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(GameOptions::default().validate_pieces(&tall).is_err());
+        let higher = GameOptions {
+            hidden_rows: 3,
+            ..GameOptions::default()
+        };
+        assert_eq!(higher.validate_pieces(&tall), Ok(()));
+    }
+
+    #[test]
+    fn next_tetromino_spawns_after_are() {
+        let mut engine = Engine::new(
+            GameMode::Marathon,
+            GameOptions::default(),
+            standard_pieces(),
+            1,
+        );
+        engine.start();
+        let next = engine.tetromino_next.unwrap();
+        engine.tick(pressed(INPUT_HARD_DROP));
+        //The frame the tetromino locks on counts toward the delay.
+        for _ in 1..engine.are(false) {
+            assert!(matches!(engine.phase, Phase::Are(_)));
+            assert!(engine.tetromino.is_none());
+            engine.tick(FrameInput::default());
+        }
+        assert_eq!(engine.phase, Phase::Falling);
+        assert_eq!(engine.tetromino.unwrap().piece, next.piece);
+    }
+
+    #[test]
+    fn rotation_and_hold_during_are_apply_at_spawn() {
+        let mut engine = Engine::new(
+            GameMode::Marathon,
+            GameOptions::default(),
+            standard_pieces(),
+            1,
+        );
+        engine.start();
+        let next = engine.tetromino_next.unwrap();
+        engine.tick(pressed(INPUT_HARD_DROP));
+        //Rotate once while nothing is in play and keep hold down until the next tetromino spawns.
+        engine.tick(pressed(INPUT_ROTATE_CW | INPUT_HOLD));
+        while engine.phase != Phase::Falling {
+            engine.tick(held(INPUT_HOLD));
+        }
+        assert_eq!(engine.tetromino_hold.unwrap().piece, next.piece);
+        assert!(!engine.can_hold());
+        assert_eq!(engine.tetromino.unwrap().rotation.index(), 1);
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall_at_once() {
+        let options = GameOptions {
+            arr: 0,
+            ..GameOptions::default()
+        };
+        let mut engine = Engine::new(GameMode::Marathon, options, standard_pieces(), 1);
+        engine.start();
+        let leftmost = |engine: &Engine| {
+            let tetromino = engine.tetromino.unwrap();
+            cells(&engine.pieces, &tetromino)
+                .iter()
+                .map(|(x, _)| *x)
+                .min()
+                .unwrap()
+        };
+        let spawned = leftmost(&engine);
+        engine.tick(pressed(INPUT_LEFT));
+        for _ in 1..engine.das() {
+            engine.tick(held(INPUT_LEFT));
+        }
+        assert_eq!(leftmost(&engine), spawned - 1);
+        engine.tick(held(INPUT_LEFT));
+        assert_eq!(leftmost(&engine), 0);
+    }
+
+    #[test]
+    fn stack_fades_and_shows_again_at_game_over() {
+        let look_after = |stack, frames| {
+            let options = GameOptions {
+                stack,
+                ..GameOptions::default()
+            };
+            let mut engine = Engine::new(GameMode::Marathon, options, standard_pieces(), 1);
+            engine.start();
+            engine.tick(pressed(INPUT_HARD_DROP));
+            let block = *engine
+                .grid
+                .blocks
+                .iter()
+                .flatten()
+                .find(|block| block.state == BlockState::Filled)
+                .unwrap();
+            engine.frame = block.locked_at + frames;
+            let look = engine.block_look(&block);
+            engine.finish(GameEnd::TopOut);
+            assert_eq!(engine.block_look(&block), BlockLook::Solid(1.0));
+            look
+        };
+
+        let fading = StackVisibility::Fading { frames: 60 };
+        assert_eq!(look_after(fading, 0), BlockLook::Solid(1.0));
+        assert_eq!(look_after(fading, 60), BlockLook::Solid(1.0));
+        assert_eq!(
+            look_after(fading, 60 + FADE_FRAMES / 2),
+            BlockLook::Solid(0.5)
+        );
+        assert_eq!(look_after(fading, 60 + FADE_FRAMES), BlockLook::Hidden);
+        assert_eq!(look_after(StackVisibility::Invisible, 0), BlockLook::Hidden);
+        assert_eq!(
+            look_after(StackVisibility::Outline, 1000),
+            BlockLook::Outline
+        );
+    }
+
+    #[test]
+    fn lands_on_the_stack_as_it_spawns_at_20g() {
+        let mut engine = Engine::new(
+            GameMode::Master,
+            GameOptions::default(),
+            standard_pieces(),
+            1,
+        );
+        engine.master.level = 500;
+        engine.start();
+        assert!(engine.gravity() >= GRAVITY_20G);
+        engine.tick(pressed(INPUT_HARD_DROP));
+
+        //Stack up the bottom ten rows during the entry delay, leaving a hole so none clear.
+        let height = engine.grid.height;
+        for x in 1..engine.grid.width {
+            for y in height - 10..height {
+                engine.grid.blocks[x as usize][y as usize].state = BlockState::Filled;
+            }
+        }
+        while engine.phase != Phase::Falling {
+            engine.tick(FrameInput::default());
+        }
+        let tetromino = engine.tetromino.unwrap();
+        let lowest = cells(&engine.pieces, &tetromino)
+            .iter()
+            .map(|(_, y)| *y)
+            .max()
+            .unwrap();
+        assert_eq!(lowest, (height - 11) as i32);
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ai::*;
+use crate::engine::*;
+use crate::pathfinder::*;
+use crate::pieces::PieceSet;
+use crate::types::*;
+
+//Tetrominoes placed in a drill.
+pub const DRILL_PIECES: u32 = 40;
+
+//Inputs that count as presses.
+const PRESS_INPUTS: u8 =
+    INPUT_LEFT | INPUT_RIGHT | INPUT_DOWN | INPUT_ROTATE_CW | INPUT_ROTATE_CCW | INPUT_ROTATE_180;
+
+//The fewest presses that lock a tetromino where `target` is, from where it spawned. None if it
+//can't get there.
+pub fn fewest_presses(
+    board: &Board,
+    pieces: &PieceSet,
+    spawn: &Tetromino,
+    target: &Tetromino,
+) -> Option<Vec<Press>> {
+    path_to(board, pieces, spawn, target).map(|path| path.presses)
+}
+
+//How the last tetromino was placed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Judgement {
+    //Presses the player made.
+    pub presses: u32,
+    //The fewest presses for the placement, or for the drill's target when there is one.
+    pub fewest: Option<Vec<Press>>,
+    //Whether the tetromino landed on the drill's target.
+    pub hit: Option<bool>,
+}
+
+impl Judgement {
+    //A few short lines on how the tetromino was placed and how it could have been.
+    pub fn describe(&self) -> String {
+        let hit = match self.hit {
+            Some(true) => "Target hit!\n",
+            Some(false) => "Target missed.\n",
+            None => "",
+        };
+        let presses = match &self.fewest {
+            Some(fewest) if self.hit != Some(false) && self.presses <= fewest.len() as u32 => {
+                format!("{} presses, perfect", self.presses)
+            }
+            Some(fewest) => format!(
+                "{} presses, {} needed:\n  {}",
+                self.presses,
+                fewest.len(),
+                format_presses(fewest)
+            ),
+            None => format!("{} presses", self.presses),
+        };
+        format!("{}{}", hit, presses)
+    }
+}
+
+//Keeps count of the presses every tetromino takes, against the fewest it could have taken
+//(finesse), and of the targets of a drill.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Finesse {
+    //The tetromino in play as it spawned or came out of hold, before it was turned.
+    spawn: Option<Tetromino>,
+    //Presses made since the last tetromino locked. Presses made during the entry delay count
+    //toward the next tetromino.
+    presses: u32,
+    //Tetrominoes that took more presses than they needed.
+    pub faults: u32,
+    //Where the drill asks the tetromino in play to go.
+    pub target: Option<Tetromino>,
+    pub targets_hit: u32,
+    pub last: Option<Judgement>,
+}
+
+impl Finesse {
+    pub fn new() -> Finesse {
+        Finesse::default()
+    }
+
+    pub fn on_spawn(&mut self, tetromino: &Tetromino) {
+        self.spawn = Some(*tetromino);
+    }
+
+    //A tetromino that comes out of hold starts over.
+    pub fn on_hold(&mut self, tetromino: &Tetromino) {
+        self.spawn = Some(*tetromino);
+        self.presses = 0;
+    }
+
+    pub fn on_input(&mut self, input: &FrameInput) {
+        self.presses += (input.pressed & PRESS_INPUTS).count_ones();
+    }
+
+    //Judges the tetromino that is about to lock on `board`.
+    pub fn on_lock(&mut self, board: &Board, pieces: &PieceSet, placed: &Tetromino) {
+        let presses = self.presses;
+        self.presses = 0;
+        let spawn = match self.spawn.take() {
+            Some(spawn) => spawn,
+            None => return,
+        };
+
+        let mut fewest = fewest_presses(board, pieces, &spawn, placed);
+        if let Some(fewest) = &fewest {
+            if presses > fewest.len() as u32 {
+                self.faults += 1;
+            }
+        }
+
+        let mut hit = None;
+        if let Some(target) = self.target.take() {
+            let mut wanted = cells(pieces, &target);
+            wanted.sort();
+            let mut landed = cells(pieces, placed);
+            landed.sort();
+            if wanted == landed {
+                self.targets_hit += 1;
+            } else {
+                //Show how the target could have been reached instead.
+                fewest = fewest_presses(board, pieces, &spawn, &target);
+            }
+            hit = Some(wanted == landed);
+        }
+        self.last = Some(Judgement {
+            presses,
+            fewest,
+            hit,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Board, PieceSet, Tetromino) {
+        let pieces = PieceSet::standard();
+        let board = Board {
+            width: 10,
+            rows: vec![0; 22],
+        };
+        let spawn = Tetromino::spawn(0, Point2::new(5.0, 2.0), 1, &pieces);
+        (board, pieces, spawn)
+    }
+
+    fn pressed(bits: u8) -> FrameInput {
+        let mut input = FrameInput::default();
+        input.press(bits);
+        input
+    }
+
+    #[test]
+    fn dropping_where_it_spawned_is_no_fault() {
+        let (board, pieces, spawn) = setup();
+        let mut finesse = Finesse::new();
+        finesse.on_spawn(&spawn);
+        finesse.on_lock(&board, &pieces, &dropped(&board, &pieces, &spawn));
+        assert_eq!(finesse.faults, 0);
+        assert_eq!(
+            finesse.last.as_ref().unwrap().describe(),
+            "0 presses, perfect"
+        );
+    }
+
+    #[test]
+    fn wasted_presses_are_a_fault() {
+        let (board, pieces, spawn) = setup();
+        let mut finesse = Finesse::new();
+        finesse.on_spawn(&spawn);
+        finesse.on_input(&pressed(INPUT_LEFT));
+        finesse.on_input(&pressed(INPUT_RIGHT));
+        finesse.on_input(&pressed(INPUT_HARD_DROP | INPUT_HOLD));
+        finesse.on_lock(&board, &pieces, &dropped(&board, &pieces, &spawn));
+        assert_eq!(finesse.faults, 1);
+        assert_eq!(finesse.last.as_ref().unwrap().presses, 2);
+
+        //Presses are counted again for the next tetromino.
+        finesse.on_spawn(&spawn);
+        finesse.on_lock(&board, &pieces, &dropped(&board, &pieces, &spawn));
+        assert_eq!(finesse.faults, 1);
+    }
+
+    #[test]
+    fn missed_targets_show_the_way_there() {
+        let (board, pieces, spawn) = setup();
+        let mut finesse = Finesse::new();
+        let target = paths(&board, &pieces, &spawn)
+            .into_iter()
+            .find(|path| path.presses == vec![Press::DasLeft])
+            .unwrap()
+            .tetromino;
+        finesse.on_spawn(&spawn);
+        finesse.target = Some(target);
+        finesse.on_lock(&board, &pieces, &dropped(&board, &pieces, &spawn));
+        assert_eq!(finesse.targets_hit, 0);
+        assert_eq!(
+            finesse.last.as_ref().unwrap().describe(),
+            "Target missed.\n0 presses, 1 needed:\n  DAS left"
+        );
+
+        finesse.on_spawn(&spawn);
+        finesse.target = Some(target);
+        finesse.on_input(&pressed(INPUT_LEFT));
+        finesse.on_lock(&board, &pieces, &target);
+        assert_eq!(finesse.targets_hit, 1);
+        assert_eq!(finesse.faults, 0);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::engine::*;
+use crate::random::Random;
+
+//Rows sent for clearing 0 to 4 lines.
+const LINE_ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+//Rows sent for T-spins clearing 0 to 3 lines.
+const T_SPIN_ATTACK: [u32; 4] = [0, 2, 4, 6];
+//Rows added for every clear in a row after the first. The last entry holds for longer combos.
+const COMBO_ATTACK: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+//Rows added for a rustris or T-spin clear right after another one.
+const BACK_TO_BACK_BONUS: u32 = 1;
+
+//Rows of garbage a player sends and receives in a versus game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Garbage {
+    //Garbage waiting to be raised, oldest first: rows, and the column their hole is in.
+    pending: VecDeque<(u32, u32)>,
+    //Clears in a row so far.
+    combo: u32,
+    //Whether the last clear was a rustris or a T-spin.
+    back_to_back: bool,
+    //Kept apart from the engine's, so both players still get the same tetrominoes.
+    random: Random,
+}
+
+impl Default for Garbage {
+    fn default() -> Garbage {
+        Garbage::new(0)
+    }
+}
+
+impl Garbage {
+    pub fn new(seed: u64) -> Garbage {
+        Garbage {
+            pending: VecDeque::new(),
+            combo: 0,
+            back_to_back: false,
+            random: Random::new(seed ^ 0x6A09_E667_F3BC_C908),
+        }
+    }
+
+    //Works out the attack of a tetromino that cleared `lines`. It cancels pending garbage first,
+    //and whatever is left over is sent to the opponent.
+    pub fn on_lock(&mut self, lines: u32, t_spin: bool) -> u32 {
+        if lines == 0 {
+            self.combo = 0;
+            return 0;
+        }
+        let lines = lines.min(4) as usize;
+        let mut attack = if t_spin {
+            T_SPIN_ATTACK[lines.min(3)]
+        } else {
+            LINE_ATTACK[lines]
+        };
+        let difficult = t_spin || lines == 4;
+        if difficult && self.back_to_back {
+            attack += BACK_TO_BACK_BONUS;
+        }
+        self.back_to_back = difficult;
+        attack += COMBO_ATTACK[(self.combo as usize).min(COMBO_ATTACK.len() - 1)];
+        self.combo += 1;
+
+        while attack > 0 {
+            match self.pending.front_mut() {
+                Some((rows, _)) if *rows > attack => {
+                    *rows -= attack;
+                    attack = 0;
+                }
+                Some((rows, _)) => {
+                    attack -= *rows;
+                    self.pending.pop_front();
+                }
+                None => break,
+            }
+        }
+        attack
+    }
+
+    //Queues rows sent by the opponent, with their hole in a random column of a grid `width`
+    //columns wide. In big mode holes are `scale` columns wide and stay on even columns.
+    pub fn receive(&mut self, rows: u32, width: u32, scale: u32) {
+        if rows == 0 {
+            return;
+        }
+        let hole = self.random.below((width / scale) as usize) as u32 * scale;
+        self.pending.push_back((rows, hole));
+    }
+
+    pub fn pending_rows(&self) -> u32 {
+        self.pending.iter().map(|(rows, _)| rows).sum()
+    }
+
+    //Takes all the pending garbage, to be raised.
+    pub fn take(&mut self) -> Vec<(u32, u32)> {
+        self.pending.drain(..).collect()
+    }
+}
+
+//Rows the engine attacked with on the last frame.
+fn attack_sent(engine: &Engine) -> u32 {
+    engine
+        .events()
+        .iter()
+        .map(|event| match event {
+            GameEvent::Attack(rows) => *rows,
+            _ => 0,
+        })
+        .sum()
+}
+
+//Hands each player the garbage the other sent on the last frame.
+pub fn exchange_garbage(a: &mut Engine, b: &mut Engine) {
+    let from_a = attack_sent(a);
+    let from_b = attack_sent(b);
+    a.receive_garbage(from_b);
+    b.receive_garbage(from_a);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attacks_follow_the_tables() {
+        let mut garbage = Garbage::new(1);
+        assert_eq!(garbage.on_lock(1, false), 0);
+        garbage.on_lock(0, false);
+        assert_eq!(garbage.on_lock(4, false), 4);
+        garbage.on_lock(0, false);
+        //Back to back after the rustris.
+        assert_eq!(garbage.on_lock(2, true), 5);
+    }
+
+    #[test]
+    fn combos_add_rows() {
+        let mut garbage = Garbage::new(1);
+        let sent: Vec<u32> = (0..4).map(|_| garbage.on_lock(2, false)).collect();
+        assert_eq!(sent, vec![1, 1, 2, 2]);
+        garbage.on_lock(0, false);
+        assert_eq!(garbage.on_lock(2, false), 1);
+    }
+
+    #[test]
+    fn attacks_cancel_pending_garbage_oldest_first() {
+        let mut garbage = Garbage::new(1);
+        garbage.receive(2, 10, 1);
+        garbage.receive(3, 10, 1);
+        assert_eq!(garbage.pending_rows(), 5);
+        //A rustris cancels the first two rows and two of the next three.
+        assert_eq!(garbage.on_lock(4, false), 0);
+        assert_eq!(garbage.pending_rows(), 1);
+        garbage.on_lock(0, false);
+        //What's left over after cancelling is sent, back to back this time.
+        assert_eq!(garbage.on_lock(4, false), 4);
+        assert_eq!(garbage.pending_rows(), 0);
+        assert!(garbage.take().is_empty());
+    }
+
+    #[test]
+    fn big_holes_stay_on_even_columns() {
+        let mut garbage = Garbage::new(7);
+        for _ in 0..20 {
+            garbage.receive(1, 20, 2);
+        }
+        let rows = garbage.take();
+        assert_eq!(rows.len(), 20);
+        assert!(rows.iter().all(|&(_, hole)| hole % 2 == 0 && hole < 20));
+    }
+}
@@ -0,0 +1,174 @@
+use ggez::graphics::Rect;
+use ggez::nalgebra::geometry::Point2;
+
+use crate::config::Orientation;
+
+//Space around the edges of the game, in pixels.
+const MARGIN: f32 = 20.0;
+//Width of the score panel next to the grid, and height of the one under it in portrait layouts.
+const SCORE_WIDTH: f32 = 200.0;
+const SCORE_HEIGHT: f32 = 70.0;
+//Blocks taken up by a preview of the next or held piece.
+const PREVIEW_BLOCKS: f32 = 5.0;
+//Room for the lines of text shown under the grid, like the replay controls.
+const FOOTER_HEIGHT: f32 = 60.0;
+
+//Where everything in a game is drawn. The game is laid out on a canvas with blocks of a fixed
+//size, which is then scaled to fill as much of the window as it can without being stretched.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub orientation: Orientation,
+    pub block_size: f32,
+    //Screen coordinates that show the whole canvas in the middle of the window.
+    pub screen: Rect,
+    //Top left corners of the visible part of the grid, the previews, the score and the footer.
+    pub grid: Point2<f32>,
+    pub next: Point2<f32>,
+    pub hold: Point2<f32>,
+    pub score: Point2<f32>,
+    pub footer: Point2<f32>,
+    //Size of the visible part of the grid, in pixels.
+    pub grid_width: f32,
+    pub grid_height: f32,
+}
+
+impl Layout {
+    //Lays a grid of `columns` x `rows` visible blocks out in a window of the given size.
+    pub fn new(
+        window: (f32, f32),
+        orientation: Orientation,
+        block_size: f32,
+        columns: u32,
+        rows: u32,
+    ) -> Layout {
+        match orientation {
+            Orientation::Landscape => Layout::landscape(window, block_size, columns, rows),
+            Orientation::Portrait => Layout::portrait(window, block_size, columns, rows),
+            Orientation::Auto => {
+                let landscape = Layout::landscape(window, block_size, columns, rows);
+                let portrait = Layout::portrait(window, block_size, columns, rows);
+                //The one that is scaled up the most fits best.
+                if portrait.screen.w < landscape.screen.w {
+                    portrait
+                } else {
+                    landscape
+                }
+            }
+        }
+    }
+
+    //Lays out one of the two grids of a versus game, each in its own half of the window. Player 1,
+    //counted from 0, is on the left.
+    pub fn versus(
+        window: (f32, f32),
+        orientation: Orientation,
+        block_size: f32,
+        columns: u32,
+        rows: u32,
+        player: usize,
+    ) -> Layout {
+        let (width, height) = window;
+        let mut layout = Layout::new(
+            (width / 2.0, height),
+            orientation,
+            block_size,
+            columns,
+            rows,
+        );
+        //The canvas is drawn twice side by side, so the screen shows twice its width.
+        let half = layout.screen.w;
+        layout.screen.w *= 2.0;
+        if player > 0 {
+            for point in [
+                &mut layout.grid,
+                &mut layout.next,
+                &mut layout.hold,
+                &mut layout.score,
+                &mut layout.footer,
+            ]
+            .iter_mut()
+            {
+                point.x += half;
+            }
+        }
+        layout
+    }
+
+    fn landscape(window: (f32, f32), block_size: f32, columns: u32, rows: u32) -> Layout {
+        let grid_width = columns as f32 * block_size;
+        let grid_height = rows as f32 * block_size;
+        //Both sides are as wide as the widest of them, to keep the grid in the middle.
+        let side = SCORE_WIDTH.max(PREVIEW_BLOCKS * block_size + MARGIN);
+        let grid = Point2::new(side, MARGIN);
+
+        Layout {
+            orientation: Orientation::Landscape,
+            block_size,
+            screen: fit(
+                window,
+                (
+                    2.0 * side + grid_width,
+                    grid.y + grid_height + FOOTER_HEIGHT,
+                ),
+            ),
+            grid,
+            next: Point2::new(grid.x + grid_width + MARGIN, grid.y),
+            hold: Point2::new(
+                grid.x + grid_width + MARGIN,
+                grid.y + PREVIEW_BLOCKS * block_size,
+            ),
+            score: Point2::new(MARGIN, grid.y),
+            footer: Point2::new(MARGIN, grid.y + grid_height + MARGIN / 2.0),
+            grid_width,
+            grid_height,
+        }
+    }
+
+    fn portrait(window: (f32, f32), block_size: f32, columns: u32, rows: u32) -> Layout {
+        let grid_width = columns as f32 * block_size;
+        let grid_height = rows as f32 * block_size;
+        let width = grid_width.max(2.0 * PREVIEW_BLOCKS * block_size) + 2.0 * MARGIN;
+        let grid = Point2::new(
+            (width - grid_width) / 2.0,
+            MARGIN + PREVIEW_BLOCKS * block_size,
+        );
+        let score = Point2::new(MARGIN, grid.y + grid_height + MARGIN / 2.0);
+
+        Layout {
+            orientation: Orientation::Portrait,
+            block_size,
+            screen: fit(window, (width, score.y + SCORE_HEIGHT + FOOTER_HEIGHT)),
+            grid,
+            next: Point2::new(MARGIN, MARGIN),
+            hold: Point2::new(width / 2.0, MARGIN),
+            score,
+            footer: Point2::new(MARGIN, score.y + SCORE_HEIGHT),
+            grid_width,
+            grid_height,
+        }
+    }
+
+    //Middle of the visible part of the grid.
+    pub fn grid_center(&self) -> Point2<f32> {
+        Point2::new(
+            self.grid.x + self.grid_width / 2.0,
+            self.grid.y + self.grid_height / 2.0,
+        )
+    }
+}
+
+//Screen coordinates that show a canvas of the given size as large as possible in the window,
+//centered, with the same scale in both directions.
+fn fit(window: (f32, f32), canvas: (f32, f32)) -> Rect {
+    let (window_width, window_height) = window;
+    let (canvas_width, canvas_height) = canvas;
+    let scale = (window_width / canvas_width).min(window_height / canvas_height);
+    let width = window_width / scale;
+    let height = window_height / scale;
+    Rect::new(
+        (canvas_width - width) / 2.0,
+        (canvas_height - height) / 2.0,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,29 @@
+//The game and everything it is played with, shared by the window and terminal frontends. The
+//window's modules are only built with the `window` feature.
+pub mod ai;
+#[cfg(feature = "window")]
+pub mod audio;
+pub mod bot;
+pub mod coach;
+pub mod config;
+#[cfg(feature = "window")]
+pub mod effects;
+pub mod engine;
+pub mod finesse;
+pub mod garbage;
+#[cfg(feature = "window")]
+pub mod layout;
+pub mod master;
+pub mod pathfinder;
+pub mod pieces;
+pub mod random;
+pub mod replay;
+#[cfg(feature = "window")]
+pub mod rustris;
+pub mod save;
+pub mod scores;
+#[cfg(feature = "window")]
+pub mod style;
+pub mod tbp;
+pub mod theme;
+pub mod types;
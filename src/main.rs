@@ -1,31 +1,61 @@
-use ggez::conf::FullscreenType;
-use ggez::conf::WindowMode;
 use ggez::event;
 use ggez::ContextBuilder;
+use std::env;
+use std::path::PathBuf;
 
-mod rustris;
-mod types;
-
-use rustris::Rustris;
+use rustris::rustris::Rustris;
+use rustris::{config, pieces, theme};
 
 fn main() {
+    //Look for resources next to the manifest while developing, and next to the game otherwise.
+    let resource_dir = match env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => PathBuf::from(manifest_dir).join("resources"),
+        Err(_) => PathBuf::from("./resources"),
+    };
+
+    let (piece_sets, errors) = pieces::load_piece_sets(&resource_dir.join("pieces"));
+    errors
+        .iter()
+        .for_each(|e| println!("Skipping piece set {}", e));
+
+    let (themes, errors) = theme::load_themes(&resource_dir.join("themes"));
+    errors.iter().for_each(|e| println!("Skipping theme {}", e));
+
+    //Replays and everything else the game writes go in the user's data directory.
+    let data_dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rustris");
+
+    //Settings go in the user's config directory. If the config file has errors they are reported
+    //and the defaults are used instead.
+    let config_path = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rustris")
+        .join("config.toml");
+    let (config, errors) = config::load_config(&config_path);
+    if !errors.is_empty() {
+        println!(
+            "Using the default config, {} has errors:",
+            config_path.display()
+        );
+        errors.iter().for_each(|e| println!("  {}", e));
+    }
+
     let (mut ctx, mut event_loop) = ContextBuilder::new("Tetris", "Chris Kritsotalakis")
-        .window_mode(WindowMode {
-            width: 800.0f32,
-            height: 600.0f32,
-            maximized: false,
-            fullscreen_type: FullscreenType::Windowed,
-            borderless: false,
-            min_width: 0.0,
-            max_width: 0.0,
-            min_height: 0.0,
-            max_height: 0.0,
-            resizable: false,
-        })
+        .add_resource_path(resource_dir)
+        .window_mode(config.window_mode())
         .build()
         .unwrap();
 
-    let mut rustris = Rustris::new(&mut ctx);
+    let mut rustris = Rustris::new(
+        &mut ctx,
+        piece_sets,
+        themes,
+        data_dir,
+        config,
+        config_path,
+        errors,
+    );
 
     match event::run(&mut ctx, &mut event_loop, &mut rustris) {
         Ok(_) => println!("Exited cleanly."),
@@ -0,0 +1,363 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+//Gravity is expressed in 1/256ths of a row per frame, like TGM does. 5120 means 20G, that is the
+//tetromino falls to the bottom of the grid on the frame it appears.
+pub const GRAVITY_1G: u32 = 256;
+pub const GRAVITY_20G: u32 = 5120;
+
+pub const MAX_LEVEL: u32 = 999;
+
+//Internal gravity per level. Each entry takes effect from its level onward.
+const GRAVITY_TABLE: [(u32, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, GRAVITY_20G),
+];
+
+//Delays in frames for every section from level 500 onward. Anything below uses the first entry.
+const TIMING_TABLE: [(u32, SectionTiming); 6] = [
+    (0, SectionTiming::new(25, 25, 14, 30, 40)),
+    (500, SectionTiming::new(25, 25, 8, 30, 25)),
+    (600, SectionTiming::new(25, 16, 8, 30, 16)),
+    (700, SectionTiming::new(16, 12, 8, 30, 12)),
+    (800, SectionTiming::new(12, 6, 8, 30, 6)),
+    (900, SectionTiming::new(12, 6, 6, 17, 6)),
+];
+
+//Score needed for each grade, from 9 up to S9.
+const GRADE_TABLE: [(u64, Grade); 18] = [
+    (0, Grade::Nine),
+    (400, Grade::Eight),
+    (800, Grade::Seven),
+    (1400, Grade::Six),
+    (2000, Grade::Five),
+    (3500, Grade::Four),
+    (5500, Grade::Three),
+    (8000, Grade::Two),
+    (12000, Grade::One),
+    (16000, Grade::S1),
+    (22000, Grade::S2),
+    (30000, Grade::S3),
+    (40000, Grade::S4),
+    (52000, Grade::S5),
+    (66000, Grade::S6),
+    (82000, Grade::S7),
+    (100_000, Grade::S8),
+    (120_000, Grade::S9),
+];
+
+//Checkpoints a player has to pass to be awarded the GM grade: (level, minimum score, maximum
+//time in frames).
+const GM_CHECKPOINTS: [(u32, u64, u32); 3] = [
+    (300, 12000, 255 * FRAMES_PER_SECOND),
+    (500, 40000, 450 * FRAMES_PER_SECOND),
+    (MAX_LEVEL, 126_000, 810 * FRAMES_PER_SECOND),
+];
+
+//Delays that change as the player moves through the sections of master mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SectionTiming {
+    pub are: u32,
+    pub line_are: u32,
+    pub das: u32,
+    pub lock_delay: u32,
+    pub line_clear: u32,
+}
+
+impl SectionTiming {
+    pub const fn new(
+        are: u32,
+        line_are: u32,
+        das: u32,
+        lock_delay: u32,
+        line_clear: u32,
+    ) -> SectionTiming {
+        SectionTiming {
+            are,
+            line_are,
+            das,
+            lock_delay,
+            line_clear,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Grade {
+    Nine,
+    Eight,
+    Seven,
+    Six,
+    Five,
+    Four,
+    Three,
+    Two,
+    One,
+    S1,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    GM,
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Grade::Nine => "9",
+            Grade::Eight => "8",
+            Grade::Seven => "7",
+            Grade::Six => "6",
+            Grade::Five => "5",
+            Grade::Four => "4",
+            Grade::Three => "3",
+            Grade::Two => "2",
+            Grade::One => "1",
+            Grade::S1 => "S1",
+            Grade::S2 => "S2",
+            Grade::S3 => "S3",
+            Grade::S4 => "S4",
+            Grade::S5 => "S5",
+            Grade::S6 => "S6",
+            Grade::S7 => "S7",
+            Grade::S8 => "S8",
+            Grade::S9 => "S9",
+            Grade::GM => "GM",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Formats a number of frames as mm:ss:cc.
+pub fn format_time(frames: u32) -> String {
+    let centis = frames * 100 / FRAMES_PER_SECOND;
+    format!(
+        "{:02}:{:02}:{:02}",
+        centis / 6000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
+
+//Keeps track of the level counter, score, grade and section times of a master mode game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Master {
+    pub level: u32,
+    pub score: u64,
+    pub combo: u32,
+    pub section_times: Vec<u32>,
+    section_start: u32,
+    gm_eligible: bool,
+    finished: bool,
+}
+
+impl Default for Master {
+    fn default() -> Master {
+        Master::new()
+    }
+}
+
+impl Master {
+    pub fn new() -> Master {
+        Master {
+            level: 0,
+            score: 0,
+            combo: 1,
+            section_times: Vec::new(),
+            section_start: 0,
+            gm_eligible: true,
+            finished: false,
+        }
+    }
+
+    pub fn gravity(&self) -> u32 {
+        GRAVITY_TABLE
+            .iter()
+            .rev()
+            .find(|(level, _)| self.level >= *level)
+            .map(|(_, gravity)| *gravity)
+            .unwrap_or(GRAVITY_TABLE[0].1)
+    }
+
+    pub fn timing(&self) -> SectionTiming {
+        TIMING_TABLE
+            .iter()
+            .rev()
+            .find(|(level, _)| self.level >= *level)
+            .map(|(_, timing)| *timing)
+            .unwrap_or(TIMING_TABLE[0].1)
+    }
+
+    //The level at which the counter will stop until a line is cleared.
+    pub fn level_stop(&self) -> u32 {
+        ((self.level / 100 + 1) * 100).min(MAX_LEVEL)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    //Advances the level counter when a new tetromino enters the grid. The counter never moves
+    //past a section stop (x99 or 998) without clearing a line.
+    pub fn on_spawn(&mut self) {
+        if self.level % 100 != 99 && self.level < MAX_LEVEL - 1 {
+            self.level += 1;
+        }
+    }
+
+    //Scores a locked tetromino and moves the counter forward by the number of lines cleared.
+    //`soft` is the number of rows the tetromino was soft dropped, `bravo` whether the grid was
+    //left empty and `frame` the time since the game started.
+    pub fn on_lock(&mut self, lines: u32, soft: u32, bravo: bool, frame: u32) {
+        if lines == 0 {
+            self.combo = 1;
+            return;
+        }
+
+        self.combo += 2 * lines - 2;
+        let bravo = if bravo { 4 } else { 1 };
+        let base = ((self.level + lines) as f32 / 4.0).ceil() as u32 + soft;
+        self.score += (base * lines * self.combo * bravo) as u64;
+
+        let previous = self.level;
+        self.level = (self.level + lines).min(MAX_LEVEL);
+
+        if self.level / 100 > previous / 100 || self.level == MAX_LEVEL {
+            self.section_times.push(frame - self.section_start);
+            self.section_start = frame;
+        }
+
+        let level = self.level;
+        let score = self.score;
+        if GM_CHECKPOINTS
+            .iter()
+            .filter(|(checkpoint, _, _)| previous < *checkpoint && level >= *checkpoint)
+            .any(|(_, min_score, max_time)| score < *min_score || frame > *max_time)
+        {
+            self.gm_eligible = false;
+        }
+
+        if self.level == MAX_LEVEL {
+            self.finished = true;
+        }
+    }
+
+    pub fn grade(&self) -> Grade {
+        if self.finished && self.gm_eligible {
+            return Grade::GM;
+        }
+        GRADE_TABLE
+            .iter()
+            .rev()
+            .find(|(score, _)| self.score >= *score)
+            .map(|(_, grade)| *grade)
+            .unwrap_or(Grade::Nine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_stops_at_the_section_until_a_line_is_cleared() {
+        let mut master = Master::new();
+        for _ in 0..150 {
+            master.on_spawn();
+        }
+        assert_eq!(master.level, 99);
+        assert_eq!(master.level_stop(), 100);
+
+        master.on_lock(1, 0, false, 600);
+        assert_eq!(master.level, 100);
+        assert_eq!(master.section_times, vec![600]);
+        master.on_lock(2, 0, false, 900);
+        assert_eq!(master.section_times, vec![600]);
+    }
+
+    #[test]
+    fn timing_and_gravity_follow_the_level() {
+        let mut master = Master::new();
+        assert_eq!(master.gravity(), 4);
+        assert_eq!(master.timing(), TIMING_TABLE[0].1);
+        master.level = 500;
+        assert_eq!(master.gravity(), GRAVITY_20G);
+        assert_eq!(master.timing(), TIMING_TABLE[1].1);
+        master.level = 998;
+        assert_eq!(master.timing(), SectionTiming::new(12, 6, 6, 17, 6));
+    }
+
+    #[test]
+    fn grades_follow_the_score() {
+        let mut master = Master::new();
+        assert_eq!(master.grade(), Grade::Nine);
+        master.score = 12000;
+        assert_eq!(master.grade(), Grade::One);
+        master.score = 1_000_000;
+        assert_eq!(master.grade(), Grade::S9);
+    }
+
+    //Clears `lines` lines at a time every `seconds` until the game is over.
+    fn play_to_the_end(master: &mut Master, lines: u32, seconds: u32) {
+        let mut frame = 0;
+        while !master.is_finished() {
+            frame += seconds * FRAMES_PER_SECOND;
+            master.on_lock(lines, 0, false, frame);
+        }
+    }
+
+    #[test]
+    fn gm_needs_every_checkpoint() {
+        let mut master = Master::new();
+        play_to_the_end(&mut master, 4, 1);
+        assert_eq!(master.level, MAX_LEVEL);
+        assert_eq!(master.section_times.len(), 10);
+        assert_eq!(master.grade(), Grade::GM);
+
+        //Level 300 is reached too late.
+        let mut master = Master::new();
+        play_to_the_end(&mut master, 1, 1);
+        assert_eq!(master.level, MAX_LEVEL);
+        assert!(master.grade() < Grade::GM);
+    }
+
+    #[test]
+    fn times_are_minutes_seconds_and_hundredths() {
+        assert_eq!(format_time(0), "00:00:00");
+        assert_eq!(format_time(61 * FRAMES_PER_SECOND + 30), "01:01:50");
+    }
+}
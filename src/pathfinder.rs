@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+use crate::ai::{cells, dropped, Board};
+use crate::engine::*;
+use crate::pieces::PieceSet;
+use crate::types::*;
+
+//A key press that moves a tetromino, the way a player makes them. Holding Left or Right until the
+//tetromino reaches the wall, and holding Down until it reaches the stack, count as a single press.
+//Every path ends with a hard drop, which isn't listed.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Press {
+    Left,
+    Right,
+    DasLeft,
+    DasRight,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    SoftDrop,
+}
+
+//In the order paths try them, so the simpler of two equally long paths is found first.
+const PRESSES: [Press; 8] = [
+    Press::Left,
+    Press::Right,
+    Press::DasLeft,
+    Press::DasRight,
+    Press::RotateCw,
+    Press::RotateCcw,
+    Press::Rotate180,
+    Press::SoftDrop,
+];
+
+impl Press {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Press::Left => "Left",
+            Press::Right => "Right",
+            Press::DasLeft => "DAS left",
+            Press::DasRight => "DAS right",
+            Press::RotateCw => "CW",
+            Press::RotateCcw => "CCW",
+            Press::Rotate180 => "180",
+            Press::SoftDrop => "Soft drop",
+        }
+    }
+
+    //The input the press is made with.
+    pub fn input(&self) -> u8 {
+        match self {
+            Press::Left | Press::DasLeft => INPUT_LEFT,
+            Press::Right | Press::DasRight => INPUT_RIGHT,
+            Press::RotateCw => INPUT_ROTATE_CW,
+            Press::RotateCcw => INPUT_ROTATE_CCW,
+            Press::Rotate180 => INPUT_ROTATE_180,
+            Press::SoftDrop => INPUT_DOWN,
+        }
+    }
+
+    pub fn is_rotation(&self) -> bool {
+        matches!(self, Press::RotateCw | Press::RotateCcw | Press::Rotate180)
+    }
+
+    //Where the press takes a tetromino on `board`, if it moves at all.
+    pub fn apply(
+        &self,
+        board: &Board,
+        pieces: &PieceSet,
+        tetromino: &Tetromino,
+    ) -> Option<Tetromino> {
+        let collides = |moved: &Tetromino| board.collides(pieces, moved);
+        let direction = match self {
+            Press::Left | Press::DasLeft => MoveDirection::Left,
+            Press::Right | Press::DasRight => MoveDirection::Right,
+            Press::RotateCw => MoveDirection::Up,
+            Press::RotateCcw => MoveDirection::RotateCcw,
+            Press::Rotate180 => MoveDirection::Rotate180,
+            Press::SoftDrop => MoveDirection::Down,
+        };
+        let mut moved = moved_tetromino(tetromino, &direction, pieces, collides)?;
+        if let Press::DasLeft | Press::DasRight | Press::SoftDrop = self {
+            while let Some(next) = moved_tetromino(&moved, &direction, pieces, collides) {
+                moved = next;
+            }
+        }
+        Some(moved)
+    }
+}
+
+//Lists presses the way they are shown to the player.
+pub fn format_presses(presses: &[Press]) -> String {
+    if presses.is_empty() {
+        return String::from("just drop");
+    }
+    presses
+        .iter()
+        .map(|press| press.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+//A place a tetromino can be locked, and the fewest presses that get it there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    //The tetromino where it comes to rest.
+    pub tetromino: Tetromino,
+    pub presses: Vec<Press>,
+    //Whether the last press turns the tetromino into the place it rests in, which is how T-spins
+    //are made.
+    pub spin: bool,
+}
+
+//Every place a tetromino can be locked on `board`, starting from where it is, with the shortest
+//path to each. Unlike hard drops alone this finds tucks under overhangs and spins into slots.
+//Placements that fill the same cells are only listed once, and once more if a spin gets there
+//too. Gravity is left out, so paths are the ones a player could make with the tetromino floating
+//where it spawned.
+pub fn paths(board: &Board, pieces: &PieceSet, spawn: &Tetromino) -> Vec<Path> {
+    let mut found = Vec::new();
+    search(board, pieces, spawn, |path| {
+        found.push(path);
+        false
+    });
+    found
+}
+
+//The shortest path that locks a tetromino where `target` fills, if it can get there at all.
+pub fn path_to(
+    board: &Board,
+    pieces: &PieceSet,
+    spawn: &Tetromino,
+    target: &Tetromino,
+) -> Option<Path> {
+    let goal = sorted_cells(pieces, target);
+    let mut found = None;
+    search(board, pieces, spawn, |path| {
+        if sorted_cells(pieces, &path.tetromino) == goal {
+            found = Some(path);
+            return true;
+        }
+        false
+    });
+    found
+}
+
+fn sorted_cells(pieces: &PieceSet, tetromino: &Tetromino) -> Vec<(i32, i32)> {
+    let mut cells = cells(pieces, tetromino);
+    cells.sort();
+    cells
+}
+
+//Goes through every place the tetromino can be moved to, nearest first, and hands the placements
+//it finds to `visit` until it returns true.
+fn search<F: FnMut(Path) -> bool>(
+    board: &Board,
+    pieces: &PieceSet,
+    spawn: &Tetromino,
+    mut visit: F,
+) {
+    //Turning into a place is told apart from moving into it, so spins aren't cut off by a shorter
+    //path that gets there without one. Rotations that leave the cells as they are (an O) don't
+    //turn the tetromino.
+    let key = |tetromino: &Tetromino, turned: bool| {
+        (
+            tetromino.position.x as i32,
+            tetromino.position.y as i32,
+            tetromino.rotation.index(),
+            turned,
+        )
+    };
+    let mut seen = HashSet::new();
+    let mut placed = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(key(spawn, false));
+    queue.push_back((*spawn, Vec::new(), false));
+
+    while let Some((tetromino, presses, turned)) = queue.pop_front() {
+        let rest = dropped(board, pieces, &tetromino);
+        let spin = turned && rest == tetromino;
+        //A spin into cells a plain drop also fills is a different placement: it scores differently.
+        if placed.insert((sorted_cells(pieces, &rest), spin)) {
+            let path = Path {
+                tetromino: rest,
+                presses: presses.clone(),
+                spin,
+            };
+            if visit(path) {
+                return;
+            }
+        }
+        for press in PRESSES.iter() {
+            if let Some(next) = press.apply(board, pieces, &tetromino) {
+                let turned = press.is_rotation()
+                    && sorted_cells(pieces, &next) != sorted_cells(pieces, &tetromino);
+                if seen.insert(key(&next, turned)) {
+                    let mut presses = presses.clone();
+                    presses.push(*press);
+                    queue.push_back((next, presses, turned));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //An empty board of the standard size, and the piece named `name` where it spawns on it.
+    fn setup(name: &str) -> (Board, PieceSet, Tetromino) {
+        let pieces = PieceSet::standard();
+        let piece = pieces
+            .pieces
+            .iter()
+            .position(|piece| piece.name == name)
+            .unwrap();
+        let options = GameOptions::default();
+        let board = Board {
+            width: options.width,
+            rows: vec![0; (options.height + options.hidden_rows) as usize],
+        };
+        let spawn = Tetromino::spawn(piece, Point2::new(5.0, 2.0), 1, &pieces);
+        (board, pieces, spawn)
+    }
+
+    //Fills the given columns of a row counted from the floor up.
+    fn fill(board: &mut Board, row: usize, columns: &[u32]) {
+        let y = board.rows.len() - 1 - row;
+        for x in columns {
+            board.rows[y] |= 1 << x;
+        }
+    }
+
+    fn path_filling<'a>(
+        paths: &'a [Path],
+        pieces: &PieceSet,
+        cells: &[(i32, i32)],
+    ) -> Option<&'a Path> {
+        let mut cells = cells.to_vec();
+        cells.sort();
+        paths
+            .iter()
+            .find(|path| sorted_cells(pieces, &path.tetromino) == cells)
+    }
+
+    #[test]
+    fn o_has_nine_places_on_an_empty_board() {
+        let (board, pieces, spawn) = setup("O");
+        let found = paths(&board, &pieces, &spawn);
+        assert_eq!(found.len(), 9);
+        assert!(found[0].presses.is_empty());
+    }
+
+    #[test]
+    fn tucks_under_an_overhang() {
+        let (mut board, pieces, spawn) = setup("O");
+        //A shaft in columns 2 and 3 leads to a gap under the overhang in columns 0 and 1.
+        fill(&mut board, 0, &[4, 5, 6, 7, 8, 9]);
+        fill(&mut board, 1, &[4, 5, 6, 7, 8, 9]);
+        fill(&mut board, 2, &[0, 1]);
+        let bottom = board.rows.len() as i32 - 1;
+        let found = paths(&board, &pieces, &spawn);
+        let tuck = path_filling(
+            &found,
+            &pieces,
+            &[(0, bottom), (1, bottom), (0, bottom - 1), (1, bottom - 1)],
+        )
+        .unwrap();
+        assert_eq!(tuck.presses.len(), 4);
+        assert_eq!(tuck.presses.last(), Some(&Press::DasLeft));
+        assert!(!tuck.spin);
+    }
+
+    #[test]
+    fn spins_into_a_t_slot() {
+        let (mut board, pieces, spawn) = setup("T");
+        //The slot in the middle of the bottom two rows is covered on its left.
+        fill(&mut board, 0, &[0, 1, 2, 3, 5, 6, 7, 8, 9]);
+        fill(&mut board, 1, &[0, 1, 2, 6, 7, 8, 9]);
+        fill(&mut board, 2, &[0, 1, 2, 3]);
+        let bottom = board.rows.len() as i32 - 1;
+        let found = paths(&board, &pieces, &spawn);
+        let slot = path_filling(
+            &found,
+            &pieces,
+            &[
+                (3, bottom - 1),
+                (4, bottom - 1),
+                (5, bottom - 1),
+                (4, bottom),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            slot.presses,
+            vec![
+                Press::Left,
+                Press::RotateCw,
+                Press::SoftDrop,
+                Press::RotateCcw
+            ]
+        );
+        assert!(slot.spin);
+    }
+
+    #[test]
+    fn lists_a_spin_apart_from_a_drop_into_the_same_cells() {
+        let (mut board, pieces, spawn) = setup("T");
+        //The same slot as above, left open so a plain drop gets in too.
+        fill(&mut board, 0, &[0, 1, 2, 3, 5, 6, 7, 8, 9]);
+        fill(&mut board, 1, &[0, 1, 2, 6, 7, 8, 9]);
+        let bottom = board.rows.len() as i32 - 1;
+        let mut cells = vec![
+            (3, bottom - 1),
+            (4, bottom - 1),
+            (5, bottom - 1),
+            (4, bottom),
+        ];
+        cells.sort();
+        let found = paths(&board, &pieces, &spawn);
+        let into_slot = found
+            .iter()
+            .filter(|path| sorted_cells(&pieces, &path.tetromino) == cells)
+            .collect::<Vec<_>>();
+        assert_eq!(into_slot.len(), 2);
+        assert!(!into_slot[0].spin);
+        assert!(into_slot[1].spin);
+    }
+}
@@ -0,0 +1,424 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::types::Color;
+
+//Most cells a single piece may have, and how far from its center they may lie.
+pub const MAX_CELLS: usize = 16;
+pub const MAX_CELL_DISTANCE: i32 = 4;
+
+//I
+const COLOR_I: Color = Color::new(42.0 / 255.0, 80.0 / 255.0, 230.0 / 255.0, 255.0 / 255.0);
+//O
+const COLOR_O: Color = Color::new(255.0 / 255.0, 242.0 / 255.0, 117.0 / 255.0, 255.0 / 255.0);
+//T
+const COLOR_T: Color = Color::new(230.0 / 255.0, 46.0 / 255.0, 187.0 / 255.0, 255.0 / 255.0);
+//S
+const COLOR_S: Color = Color::new(51.0 / 255.0, 243.0 / 255.0, 115.0 / 255.0, 255.0 / 255.0);
+//Z
+const COLOR_Z: Color = Color::new(255.0 / 255.0, 136.0 / 255.0, 16.0 / 255.0, 255.0 / 255.0);
+//J
+const COLOR_J: Color = Color::new(31.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0);
+//L
+const COLOR_L: Color = Color::new(208.0 / 255.0, 54.0 / 255.0, 54.0 / 255.0, 255.0 / 255.0);
+
+pub type Cell = (i32, i32);
+
+//Offsets tried in order when a piece rotates, for each rotation state it rotates from. A
+//positive y moves the piece up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KickTable {
+    pub cw: [Vec<Cell>; 4],
+    pub ccw: [Vec<Cell>; 4],
+}
+
+impl KickTable {
+    //Looks up one of the built in kick tables:
+    //none: the piece only rotates in place.
+    //basic: the piece is pushed up to two cells sideways, which keeps it off the walls.
+    pub fn named(name: &str) -> Option<KickTable> {
+        let tests = match name {
+            "none" => vec![(0, 0)],
+            "basic" => vec![(0, 0), (1, 0), (-1, 0), (2, 0), (-2, 0)],
+            _ => return None,
+        };
+        Some(KickTable {
+            cw: states(vec![tests.clone(); 4]),
+            ccw: states(vec![tests; 4]),
+        })
+    }
+}
+
+//A single piece: its cells in every rotation state, color, kicks and where it spawns relative
+//to the usual spawn position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceDef {
+    pub name: String,
+    pub color: Color,
+    pub rotations: [Vec<Cell>; 4],
+    pub kicks: KickTable,
+    pub spawn_offset: Cell,
+}
+
+impl PieceDef {
+    fn new(name: &str, color: Color, rotations: Vec<Vec<Cell>>) -> PieceDef {
+        PieceDef {
+            name: String::from(name),
+            color,
+            rotations: states(rotations),
+            kicks: KickTable::named("basic").unwrap(),
+            spawn_offset: (0, 0),
+        }
+    }
+}
+
+//The pieces a game is played with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceSet {
+    pub name: String,
+    pub pieces: Vec<PieceDef>,
+}
+
+impl PieceSet {
+    //The seven tetrominoes.
+    pub fn standard() -> PieceSet {
+        PieceSet {
+            name: String::from("Standard"),
+            pieces: vec![
+                PieceDef::new(
+                    "I",
+                    COLOR_I,
+                    vec![
+                        vec![(-2, 0), (-1, 0), (0, 0), (1, 0)],
+                        vec![(0, 2), (0, 1), (0, 0), (0, -1)],
+                        vec![(-2, 0), (-1, 0), (0, 0), (1, 0)],
+                        vec![(0, 2), (0, 1), (0, 0), (0, -1)],
+                    ],
+                ),
+                PieceDef::new("O", COLOR_O, vec![vec![(0, 0), (1, 0), (0, 1), (1, 1)]; 4]),
+                PieceDef::new(
+                    "T",
+                    COLOR_T,
+                    vec![
+                        vec![(0, 0), (-1, 0), (1, 0), (0, 1)],
+                        vec![(0, 0), (0, 1), (0, -1), (1, 0)],
+                        vec![(0, 0), (-1, 0), (1, 0), (0, -1)],
+                        vec![(0, 0), (0, 1), (0, -1), (-1, 0)],
+                    ],
+                ),
+                PieceDef::new(
+                    "S",
+                    COLOR_S,
+                    vec![
+                        vec![(0, 0), (1, 0), (0, -1), (-1, -1)],
+                        vec![(0, 0), (0, -1), (-1, 0), (-1, 1)],
+                        vec![(0, 0), (1, 0), (0, -1), (-1, -1)],
+                        vec![(0, 0), (0, -1), (-1, 0), (-1, 1)],
+                    ],
+                ),
+                PieceDef::new(
+                    "Z",
+                    COLOR_Z,
+                    vec![
+                        vec![(0, 0), (-1, 0), (0, -1), (1, -1)],
+                        vec![(0, 0), (0, 1), (-1, 0), (-1, -1)],
+                        vec![(0, 0), (-1, 0), (0, -1), (1, -1)],
+                        vec![(0, 0), (0, 1), (-1, 0), (-1, -1)],
+                    ],
+                ),
+                PieceDef::new(
+                    "J",
+                    COLOR_J,
+                    vec![
+                        vec![(0, 0), (-1, 0), (-1, 1), (1, 0)],
+                        vec![(0, 0), (0, 1), (1, 1), (0, -1)],
+                        vec![(0, 0), (1, 0), (1, -1), (-1, 0)],
+                        vec![(0, 0), (0, -1), (-1, -1), (0, 1)],
+                    ],
+                ),
+                PieceDef::new(
+                    "L",
+                    COLOR_L,
+                    vec![
+                        vec![(0, 0), (-1, 0), (-1, -1), (1, 0)],
+                        vec![(0, 0), (0, 1), (-1, 1), (0, -1)],
+                        vec![(0, 0), (1, 0), (1, 1), (-1, 0)],
+                        vec![(0, 0), (0, -1), (1, -1), (0, 1)],
+                    ],
+                ),
+            ],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    //Reads a piece set from a TOML file and checks that it can be played with.
+    pub fn load(path: &Path) -> Result<PieceSet, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: PieceSetFile =
+            toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        file.into_piece_set()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+//Loads every piece set in `dir`, after the standard one. Sets that fail to load are reported
+//back instead of stopping the others from loading.
+pub fn load_piece_sets(dir: &Path) -> (Vec<PieceSet>, Vec<String>) {
+    let mut sets = vec![PieceSet::standard()];
+    let mut errors = Vec::new();
+
+    let mut paths = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+
+    for path in paths {
+        match PieceSet::load(&path) {
+            Ok(set) => sets.push(set),
+            Err(e) => errors.push(e),
+        }
+    }
+    (sets, errors)
+}
+
+//Turns a list of per rotation values into one value per rotation state.
+fn states<T: Clone>(values: Vec<T>) -> [T; 4] {
+    [
+        values[0].clone(),
+        values[1].clone(),
+        values[2].clone(),
+        values[3].clone(),
+    ]
+}
+
+//Rotates cells the same way as the built in pieces do from one rotation state to the next.
+fn rotate_cells(cells: &[Cell]) -> Vec<Cell> {
+    cells.iter().map(|(x, y)| (*y, -*x)).collect()
+}
+
+fn to_cells(cells: &[[i32; 2]]) -> Vec<Cell> {
+    cells.iter().map(|[x, y]| (*x, *y)).collect()
+}
+
+#[derive(Deserialize)]
+struct PieceSetFile {
+    name: String,
+    pieces: Vec<PieceFile>,
+}
+
+#[derive(Deserialize)]
+struct PieceFile {
+    name: String,
+    color: [u8; 3],
+    //Either one list of cells, which is rotated to get the other states, or one list for each of
+    //the four rotation states.
+    rotations: Vec<Vec<[i32; 2]>>,
+    #[serde(default)]
+    kicks: Option<KicksFile>,
+    #[serde(default)]
+    spawn_offset: Option<[i32; 2]>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KicksFile {
+    Named(String),
+    Custom {
+        cw: Vec<Vec<[i32; 2]>>,
+        ccw: Option<Vec<Vec<[i32; 2]>>>,
+    },
+}
+
+impl PieceSetFile {
+    fn into_piece_set(self) -> Result<PieceSet, String> {
+        if self.pieces.is_empty() {
+            return Err(String::from("a piece set needs at least one piece"));
+        }
+
+        let mut names = HashSet::new();
+        let mut pieces = Vec::new();
+        for piece in self.pieces {
+            if !names.insert(piece.name.clone()) {
+                return Err(format!("piece {} is defined more than once", piece.name));
+            }
+            let name = piece.name.clone();
+            pieces.push(
+                piece
+                    .into_piece_def()
+                    .map_err(|e| format!("piece {}: {}", name, e))?,
+            );
+        }
+
+        Ok(PieceSet {
+            name: self.name,
+            pieces,
+        })
+    }
+}
+
+impl PieceFile {
+    fn into_piece_def(self) -> Result<PieceDef, String> {
+        let rotations = match self.rotations.len() {
+            1 => {
+                let mut rotations = vec![to_cells(&self.rotations[0])];
+                for index in 1..4 {
+                    let next = rotate_cells(&rotations[index - 1]);
+                    rotations.push(next);
+                }
+                rotations
+            }
+            4 => self.rotations.iter().map(|cells| to_cells(cells)).collect(),
+            count => return Err(format!("expected 1 or 4 rotation states, got {}", count)),
+        };
+
+        let cell_count = rotations[0].len();
+        if cell_count == 0 || cell_count > MAX_CELLS {
+            return Err(format!(
+                "a piece must have between 1 and {} cells, got {}",
+                MAX_CELLS, cell_count
+            ));
+        }
+        for (state, cells) in rotations.iter().enumerate() {
+            if cells.len() != cell_count {
+                return Err(format!(
+                    "rotation state {} has {} cells instead of {}",
+                    state,
+                    cells.len(),
+                    cell_count
+                ));
+            }
+            if cells.iter().collect::<HashSet<_>>().len() != cells.len() {
+                return Err(format!("rotation state {} repeats a cell", state));
+            }
+            if cells
+                .iter()
+                .any(|(x, y)| x.abs() > MAX_CELL_DISTANCE || y.abs() > MAX_CELL_DISTANCE)
+            {
+                return Err(format!(
+                    "rotation state {} has cells more than {} away from the center",
+                    state, MAX_CELL_DISTANCE
+                ));
+            }
+        }
+
+        let kicks = match self.kicks {
+            None => KickTable::named("basic").unwrap(),
+            Some(KicksFile::Named(name)) => {
+                KickTable::named(&name).ok_or_else(|| format!("unknown kick table {}", name))?
+            }
+            Some(KicksFile::Custom { cw, ccw }) => {
+                let ccw = ccw.unwrap_or_else(|| {
+                    cw.iter()
+                        .map(|tests| tests.iter().map(|[x, y]| [-*x, *y]).collect())
+                        .collect()
+                });
+                if cw.len() != 4 || ccw.len() != 4 {
+                    return Err(String::from(
+                        "kick tables need tests for each of the 4 rotation states",
+                    ));
+                }
+                KickTable {
+                    cw: states(cw.iter().map(|tests| to_cells(tests)).collect()),
+                    ccw: states(ccw.iter().map(|tests| to_cells(tests)).collect()),
+                }
+            }
+        };
+
+        let [r, g, b] = self.color;
+        let [x, y] = self.spawn_offset.unwrap_or([0, 0]);
+        Ok(PieceDef {
+            name: self.name,
+            color: Color::from_rgb(r, g, b),
+            rotations: states(rotations),
+            kicks,
+            spawn_offset: (x, y),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<PieceSet, String> {
+        toml::from_str::<PieceSetFile>(contents)
+            .map_err(|e| e.to_string())?
+            .into_piece_set()
+    }
+
+    #[test]
+    fn shipped_sets_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/pieces");
+        let (sets, errors) = load_piece_sets(&dir);
+        assert!(errors.is_empty(), "{:?}", errors);
+        //The standard set comes first, then the four shipped ones.
+        assert_eq!(sets.len(), 5);
+        assert_eq!(sets[0], PieceSet::standard());
+        let pentominoes = sets.iter().find(|set| set.name == "Pentominoes").unwrap();
+        assert_eq!(pentominoes.len(), 18);
+    }
+
+    #[test]
+    fn one_rotation_state_is_rotated_into_four() {
+        let set = parse(
+            r#"
+            name = "Test"
+            [[pieces]]
+            name = "L3"
+            color = [1, 2, 3]
+            rotations = [[[0, -1], [0, 0], [1, 0]]]
+            "#,
+        )
+        .unwrap();
+        let rotations = &set.pieces[0].rotations;
+        assert!(rotations.iter().all(|cells| cells.len() == 3));
+        assert_ne!(rotations[0], rotations[1]);
+    }
+
+    #[test]
+    fn invalid_sets_are_rejected() {
+        let piece = |fields: &str| {
+            parse(&format!(
+                "name = \"Test\"\n[[pieces]]\nname = \"X\"\ncolor = [1, 2, 3]\n{}",
+                fields
+            ))
+        };
+        assert!(parse("name = \"Test\"\npieces = []").is_err());
+        assert!(piece("rotations = [[[0, 0]], [[0, 0]]]").is_err());
+        assert!(piece("rotations = [[]]").is_err());
+        assert!(piece("rotations = [[[0, 0], [0, 0]]]").is_err());
+        assert!(piece("rotations = [[[0, 0], [5, 0]]]").is_err());
+        assert!(piece("rotations = [[[0, 0]]]\nkicks = \"unknown\"").is_err());
+        assert!(piece("rotations = [[[0, 0]]]\nkicks = { cw = [[[0, 0]]] }").is_err());
+        assert!(piece("rotations = [[[0, 0]]]").is_ok());
+
+        let duplicate = parse(
+            r#"
+            name = "Test"
+            [[pieces]]
+            name = "X"
+            color = [1, 2, 3]
+            rotations = [[[0, 0]]]
+            [[pieces]]
+            name = "X"
+            color = [1, 2, 3]
+            rotations = [[[0, 0]]]
+            "#,
+        );
+        assert_eq!(duplicate.unwrap_err(), "piece X is defined more than once");
+    }
+}
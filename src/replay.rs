@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::*;
+use crate::pieces::PieceSet;
+
+//Version of the replay format. Replays of other versions are not played, as the game may have
+//changed in ways that make them play out differently.
+pub const REPLAY_VERSION: u32 = 2;
+
+//Most frames of inputs read from a save or a script, ten hours of play, so a damaged one can't
+//run out of memory.
+pub const MAX_INPUT_FRAMES: usize = 10 * 60 * 60 * 60;
+
+//Playback speeds, from slowest to fastest.
+pub const PLAYBACK_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+//A recorded game: the seed and rules it was played with, and the inputs of every frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub mode: GameMode,
+    pub options: GameOptions,
+    pub piece_set: String,
+    //How the game went, shown in the replay browser.
+    pub summary: String,
+    pub inputs: Vec<FrameInput>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, mode: GameMode, options: GameOptions, piece_set: &str) -> Replay {
+        Replay {
+            seed,
+            mode,
+            options,
+            piece_set: String::from(piece_set),
+            summary: String::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, input: FrameInput) {
+        self.inputs.push(input);
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.inputs.len() as u32
+    }
+
+    //Starts the recorded game over, with the piece set it was played with.
+    pub fn engine(&self, piece_sets: &[Arc<PieceSet>]) -> Result<Engine, String> {
+        let pieces = piece_sets
+            .iter()
+            .find(|set| set.name == self.piece_set)
+            .ok_or_else(|| format!("unknown piece set {}", self.piece_set))?;
+        self.options.validate()?;
+        self.options.validate_pieces(pieces)?;
+
+        let mut engine = Engine::new(self.mode, self.options, pieces.clone(), self.seed);
+        engine.start();
+        Ok(engine)
+    }
+
+    //Saves the replay in `dir` under a name made from the current time, and returns its path.
+    //Replays already there are never overwritten.
+    pub fn save_new(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or(0);
+        let mut path = dir.join(format!("replay-{}.toml", time));
+        let mut copy = 1;
+        while path.exists() {
+            copy += 1;
+            path = dir.join(format!("replay-{}-{}.toml", time, copy));
+        }
+        self.save(&path)?;
+        Ok(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut file = ReplayFile {
+            version: REPLAY_VERSION,
+            seed: format!("{:016x}", self.seed),
+            mode: self.mode,
+            piece_set: self.piece_set.clone(),
+            summary: self.summary.clone(),
+            frames: self.frames(),
+            inputs: encode_inputs(&self.inputs),
+            checksum: String::new(),
+            options: self.options,
+        };
+        file.checksum = file.checksum()?;
+
+        let contents = toml::to_string(&file).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    //Reads a replay, rejecting it if it was altered or recorded by another version of the game.
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: ReplayFile =
+            toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        file.into_replay()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+//Loads every replay in `dir`, newest first. Replays that fail to load are listed with the reason.
+pub fn list_replays(dir: &Path) -> Vec<(PathBuf, Result<Replay, String>)> {
+    let mut paths = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths.reverse();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let replay = Replay::load(&path);
+            (path, replay)
+        })
+        .collect()
+}
+
+//Plays a replay back. It can be paused, sped up, slowed down and moved to any frame.
+pub struct Playback {
+    pub replay: Replay,
+    pub engine: Engine,
+    pub paused: bool,
+    pieces: Vec<Arc<PieceSet>>,
+    //Recorded frames played so far.
+    position: usize,
+    speed: usize,
+    //Frames due to be played, when playing slower or faster than normal.
+    progress: f32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, piece_sets: &[Arc<PieceSet>]) -> Result<Playback, String> {
+        Ok(Playback {
+            engine: replay.engine(piece_sets)?,
+            replay,
+            paused: false,
+            pieces: piece_sets.to_vec(),
+            position: 0,
+            speed: 2,
+            progress: 0.0,
+        })
+    }
+
+    pub fn position(&self) -> u32 {
+        self.position as u32
+    }
+
+    pub fn speed(&self) -> f32 {
+        PLAYBACK_SPEEDS[self.speed]
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(PLAYBACK_SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.replay.inputs.len()
+    }
+
+    //Called once every frame of real time. Plays as many recorded frames as the speed calls for.
+    pub fn update(&mut self) {
+        if self.paused || self.is_finished() {
+            return;
+        }
+        self.progress += self.speed();
+        while self.progress >= 1.0 {
+            self.progress -= 1.0;
+            self.step();
+        }
+    }
+
+    //Plays a single recorded frame.
+    pub fn step(&mut self) {
+        if let Some(input) = self.replay.inputs.get(self.position) {
+            self.engine.tick(*input);
+            self.position += 1;
+        }
+    }
+
+    //Moves the playback to the given frame. Going back plays the game again from the start.
+    pub fn seek(&mut self, frame: u32) -> Result<(), String> {
+        let frame = (frame as usize).min(self.replay.inputs.len());
+        if frame < self.position {
+            self.engine = self.replay.engine(&self.pieces)?;
+            self.position = 0;
+        }
+        while self.position < frame {
+            self.step();
+        }
+        self.progress = 0.0;
+        Ok(())
+    }
+}
+
+//A replay as it is stored on disk. The inputs are run length encoded and the checksum covers the
+//rest of the file.
+#[derive(Clone, Serialize, Deserialize)]
+struct ReplayFile {
+    version: u32,
+    seed: String,
+    mode: GameMode,
+    piece_set: String,
+    summary: String,
+    frames: u32,
+    inputs: String,
+    checksum: String,
+    options: GameOptions,
+}
+
+impl ReplayFile {
+    //FNV-1a hash of the file with the checksum left out.
+    fn checksum(&self) -> Result<String, String> {
+        let mut unsigned = self.clone();
+        unsigned.checksum = String::new();
+        let contents = toml::to_string(&unsigned).map_err(|e| e.to_string())?;
+
+        let hash = contents
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+        Ok(format!("{:016x}", hash))
+    }
+
+    fn into_replay(self) -> Result<Replay, String> {
+        if self.version != REPLAY_VERSION {
+            return Err(format!(
+                "recorded with replay version {}, expected {}",
+                self.version, REPLAY_VERSION
+            ));
+        }
+        if self.checksum()? != self.checksum {
+            return Err(String::from(
+                "checksum does not match, the replay was altered",
+            ));
+        }
+
+        let seed = u64::from_str_radix(&self.seed, 16).map_err(|_| "invalid seed")?;
+        //The header is only trusted as far as the most inputs any replay is read with.
+        let max_frames = MAX_INPUT_FRAMES.min(self.frames as usize);
+        let inputs = decode_inputs(&self.inputs, max_frames)?;
+        if inputs.len() != self.frames as usize {
+            return Err(format!(
+                "expected {} frames of inputs, got {}",
+                self.frames,
+                inputs.len()
+            ));
+        }
+
+        Ok(Replay {
+            seed,
+            mode: self.mode,
+            options: self.options,
+            piece_set: self.piece_set,
+            summary: self.summary,
+            inputs,
+        })
+    }
+}
+
+//Writes inputs as runs of identical frames: the pressed and held bits in hex, followed by `*`
+//and the length of the run if it is longer than a frame.
+pub fn encode_inputs(inputs: &[FrameInput]) -> String {
+    let mut runs: Vec<(FrameInput, u32)> = Vec::new();
+    for input in inputs {
+        match runs.last_mut() {
+            Some((last, count)) if last == input => *count += 1,
+            _ => runs.push((*input, 1)),
+        }
+    }
+
+    runs.iter()
+        .map(|(input, count)| {
+            if *count == 1 {
+                format!("{:02x}{:02x}", input.pressed, input.held)
+            } else {
+                format!("{:02x}{:02x}*{}", input.pressed, input.held, count)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+//Reads inputs written by `encode_inputs`, failing as soon as there are more than `max_frames`.
+pub fn decode_inputs(encoded: &str, max_frames: usize) -> Result<Vec<FrameInput>, String> {
+    let mut inputs = Vec::new();
+    for run in encoded.split_whitespace() {
+        let invalid = || format!("invalid input run {}", run);
+        let (bits, count) = match run.find('*') {
+            Some(index) => (
+                &run[..index],
+                run[index + 1..].parse::<usize>().map_err(|_| invalid())?,
+            ),
+            None => (run, 1),
+        };
+        if !bits.is_ascii() || bits.len() != 4 {
+            return Err(invalid());
+        }
+        let input = FrameInput {
+            pressed: u8::from_str_radix(&bits[..2], 16).map_err(|_| invalid())?,
+            held: u8::from_str_radix(&bits[2..], 16).map_err(|_| invalid())?,
+        };
+        if count > max_frames - inputs.len() {
+            return Err(format!("more than {} frames of inputs", max_frames));
+        }
+        inputs.extend(std::iter::repeat_n(input, count));
+    }
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(pressed: u8, held: u8) -> FrameInput {
+        FrameInput { pressed, held }
+    }
+
+    fn recorded() -> Replay {
+        let mut replay = Replay::new(1234, GameMode::Marathon, GameOptions::default(), "Standard");
+        replay.summary = String::from("Game over! Score: 0");
+        for _ in 0..30 {
+            replay.record(input(0, 0));
+        }
+        replay.record(input(INPUT_LEFT, INPUT_LEFT));
+        replay.record(input(0, INPUT_LEFT));
+        replay.record(input(INPUT_HARD_DROP, 0));
+        replay
+    }
+
+    //A directory of its own for a test to write replays in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustris-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn inputs_round_trip() {
+        let inputs = recorded().inputs;
+        let encoded = encode_inputs(&inputs);
+        assert_eq!(encoded, "0000*30 0101 0001 4000");
+        assert_eq!(decode_inputs(&encoded, inputs.len()).unwrap(), inputs);
+    }
+
+    #[test]
+    fn long_runs_are_rejected_before_they_are_read() {
+        assert!(decode_inputs("ffff*18446744073709551615", MAX_INPUT_FRAMES).is_err());
+        assert!(decode_inputs("0000*3 0000*3", 5).is_err());
+        assert_eq!(decode_inputs("0000*3 0000*2", 5).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn runs_that_are_not_hex_are_rejected() {
+        assert!(decode_inputs("a\u{e9}0", 10).is_err());
+        assert!(decode_inputs("0000 a\u{e9}0*3", 10).is_err());
+        assert!(decode_inputs("00zz", 10).is_err());
+    }
+
+    #[test]
+    fn frame_counts_past_the_limit_are_rejected() {
+        //A header claiming more frames than any game lasts, with a checksum to match.
+        let mut file = ReplayFile {
+            version: REPLAY_VERSION,
+            seed: String::from("0"),
+            mode: GameMode::Marathon,
+            piece_set: String::from("Standard"),
+            summary: String::new(),
+            frames: u32::MAX,
+            inputs: String::from("0000*4294967295"),
+            checksum: String::new(),
+            options: GameOptions::default(),
+        };
+        file.checksum = file.checksum().unwrap();
+        assert!(file.into_replay().is_err());
+    }
+
+    #[test]
+    fn replay_files_round_trip() {
+        let dir = test_dir("round-trip");
+        let replay = recorded();
+        let path = replay.save_new(&dir).unwrap();
+        assert_eq!(Replay::load(&path).unwrap(), replay);
+
+        //Changing anything but the checksum is caught.
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents.replace("Score: 0", "Score: 9")).unwrap();
+        assert!(Replay::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replays_saved_at_once_are_all_kept() {
+        let dir = test_dir("save-new");
+        let replay = recorded();
+        let first = replay.save_new(&dir).unwrap();
+        let second = replay.save_new(&dir).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(list_replays(&dir).len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn seeking_back_plays_the_game_again() {
+        let pieces = vec![Arc::new(PieceSet::standard())];
+        let mut playback = Playback::new(recorded(), &pieces).unwrap();
+        playback.seek(33).unwrap();
+        assert_eq!(playback.engine.pieces_locked, 1);
+        playback.seek(10).unwrap();
+        assert_eq!(playback.position(), 10);
+        assert_eq!(playback.engine.pieces_locked, 0);
+
+        //Without its piece set the game can't be started again, which is reported.
+        playback.pieces.clear();
+        assert!(playback.seek(0).is_err());
+    }
+}
@@ -1,351 +1,1216 @@
 use ggez::event::EventHandler;
 use ggez::event::KeyMods;
+use ggez::event::{Axis, Button, GamepadId};
 use ggez::graphics;
+use ggez::graphics::Color;
 use ggez::graphics::*;
-use ggez::input::{self, keyboard::KeyCode};
+use ggez::input::keyboard::KeyCode;
 use ggez::timer;
 use ggez::{Context, GameResult};
-use std::ops::Add;
-use std::time::{Duration, Instant};
 
+use crate::ai::*;
+use crate::audio::*;
+use crate::bot::Bot;
+use crate::coach::*;
+use crate::config::*;
+use crate::effects::*;
+use crate::engine::*;
+use crate::finesse::*;
+use crate::garbage::*;
+use crate::layout::*;
+use crate::master::*;
+use crate::pieces::PieceSet;
+use crate::replay::*;
+use crate::save::*;
+use crate::scores::*;
+use crate::style::*;
+use crate::tbp::*;
+use crate::theme::*;
 use crate::types::*;
 use ggez::nalgebra::geometry::Point2;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-const GRID_WIDTH: u32 = 10;
-const GRID_HEIGHT: u32 = 22;
+//Replays shown at once in the replay browser.
+const REPLAYS_SHOWN: usize = 15;
 
-const UPDATES_PER_SECOND: f32 = 8.0;
-const MILLIS_PER_UPDATE: u64 = (1.0 / UPDATES_PER_SECOND * 1000.0) as u64;
+//Frames a replay moves when seeking.
+const SEEK_FRAMES: u32 = 5 * FRAMES_PER_SECOND;
 
-//Struct to keep track of where our tetromino is going.
-pub enum MoveDirection {
-    Left,
-    Right,
-    Up,
-    Down,
+//Entries of the settings menu.
+const SETTINGS: [&str; 21] = [
+    "DAS",
+    "ARR",
+    "Soft drop factor",
+    "ARE",
+    "Line clear delay",
+    "Hidden rows",
+    "Block size",
+    "Window size",
+    "Fullscreen",
+    "Layout",
+    "Theme",
+    "Volume",
+    "Music volume",
+    "Effects volume",
+    "Music speeds up",
+    "Stick deadzone",
+    "Effects",
+    "Screen shake",
+    "AI difficulty",
+    "Placement hints",
+    "Controls",
+];
+
+//Window sizes that can be picked in the settings menu.
+const WINDOW_SIZES: [(u32, u32); 7] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1920, 1080),
+    (600, 800),
+    (720, 1280),
+];
+
+//What the window is showing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Screen {
+    Intro,
+    Playing,
+    //The stack of the game that just ended, so the blocks that were hidden during the game can be
+    //seen.
+    Reveal,
+    Replays,
+    Playback,
+    //The player typing their name in for a new high score.
+    NameEntry,
+    HighScores,
+    Settings,
+    //Rebinding the keys of each action.
+    Controls,
+}
+
+//Player 2 of a versus game, drawn on the right.
+struct Rival {
+    engine: Engine,
+    //Inputs made since the last frame was played.
+    input: FrameInput,
+    //Actions player 2's left stick is pushed towards, across and down.
+    stick: [Option<Action>; 2],
 }
 
 //Struct to hold the entire game state.
 pub struct Rustris {
-    grid: Grid,
-    tetromino: Option<Tetromino>,
-    tetromino_next: Option<Tetromino>,
-    game_running: bool,
-    move_tetromino_down: std::time::Duration,
-    last_update: Instant,
-    move_tetromino_every: u32,
-    to_lock: bool,
-    lock_timer: Duration,
-    score: u64,
-    single: u32,
-    double: u32,
-    tripple: u32,
-    rustris: u32,
+    engine: Engine,
+    config: Config,
+    config_path: PathBuf,
+    //Set while the config file has errors, so it isn't overwritten until the settings are saved.
+    config_broken: bool,
+    selected_setting: usize,
+    selected_action: usize,
+    //Set while waiting for the key to bind to the selected action.
+    binding: bool,
+    paused: bool,
+    sounds: Sounds,
+    effects: EffectLayer,
+    //Actions the left stick is pushed towards, across and down.
+    stick: [Option<Action>; 2],
+    //Gamepads in the order they were first used. The first one is player 1's.
+    gamepads: Vec<GamepadId>,
+    piece_sets: Vec<Arc<PieceSet>>,
+    piece_set: usize,
+    themes: Vec<Theme>,
+    //The selected theme, loaded.
+    style: Style,
+    screen: Screen,
+    last_result: Option<String>,
+    //Inputs made since the last frame was played.
+    input: FrameInput,
+    //Set while a bot is playing the game instead of the player.
+    bot: Option<Box<dyn Bot>>,
+    //Set during a versus game, and until its result has been seen.
+    rival: Option<Rival>,
+    //Where the AI would put the tetromino in play, worked out once for every tetromino: the
+    //tetrominoes locked so far and the piece in play it is for.
+    hint: Option<((u32, usize), Option<Placement>)>,
+    coach: Coach,
+    //The game being played, as it is recorded.
+    replay: Replay,
+    replay_dir: PathBuf,
+    replays: Vec<(PathBuf, Result<Replay, String>)>,
+    selected_replay: usize,
+    playback: Option<Playback>,
+    //Why the replay being watched couldn't be seeked.
+    playback_error: Option<String>,
+    //Where a game that is quit before it ends is saved.
+    save_path: PathBuf,
+    high_scores: HighScores,
+    scores_path: PathBuf,
+    //Entry for the game that just ended, if it made it onto the leaderboard.
+    new_score: Option<ScoreEntry>,
+    //Leaderboard shown on the high score screen, and the place of the newest entry on it.
+    scores_mode: GameMode,
+    highlighted_score: Option<usize>,
 }
 
 impl Rustris {
-    pub fn new(_ctx: &mut Context) -> Rustris {
+    pub fn new(
+        ctx: &mut Context,
+        piece_sets: Vec<PieceSet>,
+        themes: Vec<Theme>,
+        data_dir: PathBuf,
+        config: Config,
+        config_path: PathBuf,
+        config_errors: Vec<String>,
+    ) -> Rustris {
+        let piece_sets = piece_sets.into_iter().map(Arc::new).collect::<Vec<_>>();
+        let sounds = Sounds::new(ctx, &config.audio);
+        let effects = EffectLayer::new(&config.effects);
+        let piece_set = piece_sets
+            .iter()
+            .position(|set| set.name == config.piece_set)
+            .unwrap_or(0);
+        let theme = themes
+            .iter()
+            .find(|theme| theme.name == config.theme)
+            .unwrap_or(&themes[0]);
+        let style = Style::new(ctx, theme);
+        let scores_path = data_dir.join("scores.toml");
+        let high_scores = HighScores::load(&scores_path).unwrap_or_else(|e| {
+            println!("Couldn't load the high scores: {}", e);
+            HighScores::default()
+        });
+
         //Create new game state.
         Rustris {
-            grid: Grid::new(GRID_WIDTH, GRID_HEIGHT, Color::new(0.5, 0.5, 0.5, 1.0)),
-            tetromino: None,
-            tetromino_next: None,
-            game_running: false,
-            move_tetromino_down: Duration::new(0, 0),
-            last_update: Instant::now(),
-            move_tetromino_every: 500,
-            to_lock: false,
-            lock_timer: Duration::new(0, 0),
-            score: 0,
-            single: 0,
-            double: 0,
-            tripple: 0,
-            rustris: 0,
+            engine: Engine::new(
+                GameMode::Marathon,
+                GameOptions::default(),
+                piece_sets[0].clone(),
+                0,
+            ),
+            config,
+            config_path,
+            config_broken: !config_errors.is_empty(),
+            selected_setting: 0,
+            selected_action: 0,
+            binding: false,
+            paused: false,
+            sounds,
+            effects,
+            stick: [None; 2],
+            gamepads: Vec::new(),
+            replay: Replay::new(0, GameMode::Marathon, GameOptions::default(), ""),
+            piece_sets,
+            piece_set,
+            themes,
+            style,
+            screen: Screen::Intro,
+            last_result: if config_errors.is_empty() {
+                None
+            } else {
+                Some(format!(
+                    "The config file has errors, the defaults are used:\n{}",
+                    config_errors.join("\n")
+                ))
+            },
+            input: FrameInput::default(),
+            bot: None,
+            rival: None,
+            hint: None,
+            coach: Coach::new(),
+            replay_dir: data_dir.join("replays"),
+            save_path: data_dir.join("save.json"),
+            high_scores,
+            scores_path,
+            new_score: None,
+            scores_mode: GameMode::Marathon,
+            highlighted_score: None,
+            replays: Vec::new(),
+            selected_replay: 0,
+            playback: None,
+            playback_error: None,
         }
     }
 
     //Draw intro text here, this happens while game is not palyed.
     pub fn draw_intro(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let info_text = graphics::Text::new("Press any key to start.");
+        let mut intro = format!(
+            "Board: {}x{} (arrow keys to change)\nPieces: {} (P to change)\nBig mode: {} (B to change)\nStack: {} (S to change)\n\nPress M to start master mode.\nPress F to start the finesse drill.\nPress V for two player versus.\nPress A to watch the AI play.\nPress R to watch replays.\nPress H to see the high scores.\nPress O for settings.\nPress any other key to start.",
+            self.config.rules.width,
+            self.config.rules.height,
+            self.piece_sets[self.piece_set].name,
+            if self.config.rules.big { "on" } else { "off" },
+            self.config.rules.stack.name()
+        );
+        if let Some(program) = self.config.ai.tbp_bot.first() {
+            intro = format!("{}\nPress T to watch {} play.", intro, program);
+        }
+        if self.save_path.exists() {
+            intro = format!("{}\nPress L to resume your saved game.", intro);
+        }
+        if let Some(result) = &self.last_result {
+            intro = format!("{}\n\n{}", result, intro);
+        }
+        let info_text = self.style.text(intro);
         info_text.draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
     }
 
-    //Generates a random tetromino.
-    pub fn generate_tetromino(&mut self) {
-        if self.tetromino_next.is_none() {
-            self.tetromino_next = Some(Tetromino::random(Point2::new(5.0, 1.0), Rotation::_0));
+    //Starts a new game in the given mode.
+    pub fn start_game(&mut self, mode: GameMode) {
+        let options = self.config.game_options();
+        let pieces = self.piece_sets[self.piece_set].clone();
+        if let Err(e) = options
+            .validate()
+            .and_then(|_| options.validate_pieces(&pieces))
+        {
+            self.last_result = Some(format!("Can't start the game: {}", e));
+            return;
         }
-        self.tetromino = self.tetromino_next.take();
-        self.tetromino_next = Some(Tetromino::random(Point2::new(5.0, 1.0), Rotation::_0));
+        //Remember the rules picked on the intro screen for next time.
+        if !self.config_broken {
+            self.save_config();
+        }
+
+        let seed = rand::random::<u64>();
+        self.replay = Replay::new(seed, mode, options, &pieces.name);
+        self.engine = Engine::new(mode, options, pieces, seed);
+        self.engine.start();
+        self.input = FrameInput::default();
+        self.bot = None;
+        self.rival = None;
+        self.hint = None;
+        self.coach = Coach::new();
+        self.paused = false;
+        self.screen = Screen::Playing;
+        self.effects.reset();
+        self.sounds.start_music(0);
     }
 
-    //Calculates the offset that should be applied on the tetromino in order for its position to be
-    //a valid one in the grid
-    pub fn calculate_offset(grid: &Grid, tetromino: &Tetromino) -> Point2<f32> {
-        let mut offset = Point2::new(0.0f32, 0.0f32);
-        let grid_width = grid.width as f32 - 1.0;
+    //Starts a marathon game played by a bot.
+    pub fn start_bot_game(&mut self, bot: Box<dyn Bot>) {
+        self.start_game(GameMode::Marathon);
+        if self.screen == Screen::Playing {
+            self.bot = Some(bot);
+        }
+    }
 
-        tetromino.blocks().iter().for_each(|block| {
-            let abs_tetromino_x = tetromino.position.x + block.position.x;
-            if abs_tetromino_x >= grid_width {
-                if grid_width - abs_tetromino_x < offset.x {
-                    offset.x = grid_width - abs_tetromino_x;
-                }
-            } else if abs_tetromino_x < 0.0 {
-                if abs_tetromino_x.abs() > offset.x {
-                    offset.x = abs_tetromino_x.abs();
-                }
-            }
+    //Starts a marathon game against a second player at the same computer. Both get the same
+    //tetrominoes.
+    pub fn start_versus(&mut self) {
+        self.start_game(GameMode::Marathon);
+        if self.screen != Screen::Playing {
+            return;
+        }
+        let mut engine = Engine::new(
+            GameMode::Marathon,
+            self.engine.options,
+            self.engine.pieces.clone(),
+            self.replay.seed,
+        );
+        engine.start();
+        self.rival = Some(Rival {
+            engine,
+            input: FrameInput::default(),
+            stick: [None; 2],
         });
+    }
 
-        offset
+    //Starts a game played by the bot in the config that speaks the Tetris Bot Protocol.
+    pub fn start_tbp_game(&mut self) {
+        let supported = supports(
+            &self.config.game_options(),
+            &self.piece_sets[self.piece_set],
+        );
+        match supported.and_then(|_| TbpBot::launch(&self.config.ai.tbp_bot, false)) {
+            Ok(bot) => self.start_bot_game(Box::new(bot)),
+            Err(e) => self.last_result = Some(format!("Can't start the bot: {}", e)),
+        }
     }
 
-    pub fn get_level(&self) -> u32 {
-        let sum_lines = self.single + (self.double * 2) + (self.tripple * 3) + (self.rustris * 4);
-        if sum_lines != 0 {
-            (sum_lines as f32 / 10.0).ceil() as u32
+    //Stops the game, saves its replay and keeps a summary of it around for the intro screen.
+    pub fn end_game(&mut self) {
+        self.sounds.stop_music();
+        //Versus games aren't recorded or ranked, only won.
+        if let Some(rival) = &self.rival {
+            let result = match (self.engine.is_game_over(), rival.engine.is_game_over()) {
+                (true, true) => "Draw! Both players topped out at once.",
+                (true, false) => "Player 2 wins!",
+                _ => "Player 1 wins!",
+            };
+            self.last_result = Some(String::from(result));
+            self.new_score = None;
+            self.screen = Screen::Reveal;
+            return;
+        }
+        let mut summary = self.engine.summary();
+        if let Some(bot) = &self.bot {
+            summary = format!("{} ({})", summary, bot.name());
+        }
+        self.replay.summary = summary.clone();
+        if let Err(e) = self.replay.save_new(&self.replay_dir) {
+            println!("Couldn't save the replay: {}", e);
+        }
+        if self.config.ai.hints && self.bot.is_none() {
+            summary = format!("{}\n\n{}", summary, self.coach.summary());
+        }
+        self.last_result = Some(summary);
+
+        //Games bots played don't go on the leaderboard.
+        let entry = ScoreEntry::from_game(&self.engine, self.replay.seed);
+        self.new_score =
+            if self.bot.is_none() && self.high_scores.qualifies(self.engine.mode, &entry) {
+                Some(entry)
+            } else {
+                None
+            };
+
+        if self.engine.options.stack != StackVisibility::Visible {
+            self.screen = Screen::Reveal;
         } else {
-            1
+            self.after_game();
+        }
+    }
+
+    //Asks for the player's name if the game made it onto the leaderboard, or goes back to the
+    //intro screen.
+    fn after_game(&mut self) {
+        self.rival = None;
+        self.screen = if self.new_score.is_some() {
+            Screen::NameEntry
+        } else {
+            Screen::Intro
+        };
+    }
+
+    pub fn draw_name_entry(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let text = self.style.text(format!(
+            "New high score!\n\n{}\n\nEnter your name: {}_\n\nPress Enter when done.",
+            self.last_result.as_ref().unwrap(),
+            self.new_score.as_ref().unwrap().name
+        ));
+        text.draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
+    }
+
+    //Puts the new entry on the leaderboard and shows it.
+    fn submit_score(&mut self) {
+        let mut entry = self.new_score.take().unwrap();
+        entry.name = String::from(entry.name.trim());
+        if entry.name.is_empty() {
+            entry.name = String::from("Anonymous");
         }
+
+        let mode = self.engine.mode;
+        self.highlighted_score = self.high_scores.insert(mode, entry);
+        if let Err(e) = self.high_scores.save(&self.scores_path) {
+            println!("Couldn't save the high scores: {}", e);
+        }
+        self.scores_mode = mode;
+        self.screen = Screen::HighScores;
     }
 
-    pub fn get_move_delay(&self) -> u32 {
-        (self.move_tetromino_every as f32 / (self.get_level() as f32 / 2.0)) as u32
+    pub fn show_high_scores(&mut self) {
+        self.highlighted_score = None;
+        self.screen = Screen::HighScores;
     }
 
-    //Validates if the incoming move is a proper one. If it is it updates our tetromino with its
-    //new values.
-    pub fn validate_move(&mut self, direction: &MoveDirection) -> Option<Tetromino> {
-        let mut next_pos_empty = true;
-        let mut tetromino = self.tetromino.unwrap().clone();
+    pub fn draw_high_scores(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let table = self
+            .high_scores
+            .table(self.scores_mode)
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let cursor = if Some(index) == self.highlighted_score {
+                    ">"
+                } else {
+                    " "
+                };
+                let grade = match entry.grade {
+                    Some(grade) => format!("{:>3}  ", grade),
+                    None => String::new(),
+                };
+                format!(
+                    "{}{:2}. {:<12}  {}{:>8}  {:>4} lines  level {:>3}  {}  {}  seed {}",
+                    cursor,
+                    index + 1,
+                    entry.name,
+                    grade,
+                    entry.score,
+                    entry.lines,
+                    entry.level,
+                    format_time(entry.frames),
+                    format_date(entry.date),
+                    entry.seed
+                )
+            })
+            .collect::<Vec<String>>();
 
-        match direction {
-            MoveDirection::Left => {
-                tetromino.position.x -= 1.0;
+        let text = format!(
+            "{:?} high scores (Left/Right to change mode, Escape to go back)\n\n{}",
+            self.scores_mode,
+            if table.is_empty() {
+                String::from("No high scores yet.")
+            } else {
+                table.join("\n")
             }
-            MoveDirection::Right => {
-                tetromino.position.x += 1.0;
+        );
+        self.style
+            .text(text)
+            .draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
+    }
+
+    fn save_config(&mut self) {
+        self.config.piece_set = self.piece_sets[self.piece_set].name.clone();
+        self.config.theme = self.style.theme.name.clone();
+        if let Err(e) = self.config.save(&self.config_path) {
+            println!("Couldn't save the config: {}", e);
+        }
+    }
+
+    pub fn show_settings(&mut self) {
+        self.selected_setting = 0;
+        self.screen = Screen::Settings;
+    }
+
+    //Leaves the settings menu, saving the config and resizing the window to match it.
+    fn close_settings(&mut self, ctx: &mut Context) {
+        self.save_config();
+        self.config_broken = false;
+        if let Err(e) = graphics::set_mode(ctx, self.config.window_mode()) {
+            println!("Couldn't change the window: {}", e);
+        }
+        self.screen = Screen::Intro;
+    }
+
+    fn setting_value(&self, index: usize) -> String {
+        let config = &self.config;
+        match index {
+            0 => format!("{} frames", config.handling.das),
+            1 => format!("{} frames", config.handling.arr),
+            2 => format!("{}x", config.handling.sdf),
+            3 => format!("{} frames", config.rules.are),
+            4 => format!("{} frames", config.rules.line_clear_delay),
+            5 => format!("{}", config.rules.hidden_rows),
+            6 => format!("{} pixels", config.display.block_size),
+            7 => format!("{}x{}", config.display.width, config.display.height),
+            8 => String::from(if config.display.fullscreen {
+                "on"
+            } else {
+                "off"
+            }),
+            9 => String::from(config.display.orientation.name()),
+            10 => self.style.theme.name.clone(),
+            11 => format!("{}%", (config.audio.volume * 100.0).round()),
+            12 => format!("{}%", (config.audio.music_volume * 100.0).round()),
+            13 => format!("{}%", (config.audio.effects_volume * 100.0).round()),
+            14 => String::from(if config.audio.music_speedup {
+                "on"
+            } else {
+                "off"
+            }),
+            15 => format!("{}%", (config.gamepad.deadzone * 100.0).round()),
+            16 => String::from(if config.effects.enabled { "on" } else { "off" }),
+            17 => String::from(if config.effects.screen_shake {
+                "on"
+            } else {
+                "off"
+            }),
+            18 => String::from(config.ai.difficulty.name()),
+            19 => String::from(if config.ai.hints { "on" } else { "off" }),
+            _ => String::from("Enter to change"),
+        }
+    }
+
+    //Changes the selected setting one step up or down, staying within its bounds.
+    fn adjust_setting(&mut self, ctx: &mut Context, up: bool) {
+        let step = |value: u32, min: u32, max: u32| {
+            if up {
+                (value + 1).min(max)
+            } else {
+                value.saturating_sub(1).max(min)
+            }
+        };
+        //Volumes and the deadzone go in steps of a tenth.
+        let step_tenths = |value: f32, max: f32| {
+            step(
+                (value * 10.0).round() as u32,
+                0,
+                (max * 10.0).round() as u32,
+            ) as f32
+                / 10.0
+        };
+        //Games need a hidden row for every row a block takes up, which is two in big mode.
+        let min_hidden_rows = self.config.game_options().scale();
+        let config = &mut self.config;
+        match self.selected_setting {
+            0 => config.handling.das = step(config.handling.das, 0, MAX_DELAY),
+            1 => config.handling.arr = step(config.handling.arr, 0, MAX_DELAY),
+            2 => config.handling.sdf = step(config.handling.sdf, 1, MAX_SDF),
+            3 => config.rules.are = step(config.rules.are, 0, MAX_DELAY),
+            4 => config.rules.line_clear_delay = step(config.rules.line_clear_delay, 0, MAX_DELAY),
+            5 => {
+                config.rules.hidden_rows =
+                    step(config.rules.hidden_rows, min_hidden_rows, MAX_HIDDEN_ROWS)
+            }
+            6 => {
+                config.display.block_size =
+                    step(config.display.block_size, MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
             }
-            MoveDirection::Down => {
-                tetromino.position.y += 1.0;
+            7 => {
+                let current = WINDOW_SIZES
+                    .iter()
+                    .position(|size| *size == (config.display.width, config.display.height))
+                    .unwrap_or(1) as u32;
+                let (width, height) =
+                    WINDOW_SIZES[step(current, 0, WINDOW_SIZES.len() as u32 - 1) as usize];
+                config.display.width = width;
+                config.display.height = height;
             }
-            MoveDirection::Up => {
-                tetromino.rotation.rotate_cw();
-                let offset = Rustris::calculate_offset(&self.grid, &tetromino);
-                tetromino.position.x += offset.x;
-                tetromino.position.y += offset.y;
+            8 => config.display.fullscreen = !config.display.fullscreen,
+            9 => config.display.orientation = config.display.orientation.next(),
+            10 => {
+                let current = self
+                    .themes
+                    .iter()
+                    .position(|theme| *theme == self.style.theme)
+                    .unwrap_or(0) as u32;
+                let next = step(current, 0, self.themes.len() as u32 - 1) as usize;
+                self.style = Style::new(ctx, &self.themes[next]);
+            }
+            11 => config.audio.volume = step_tenths(config.audio.volume, 1.0),
+            12 => config.audio.music_volume = step_tenths(config.audio.music_volume, 1.0),
+            13 => config.audio.effects_volume = step_tenths(config.audio.effects_volume, 1.0),
+            14 => config.audio.music_speedup = !config.audio.music_speedup,
+            15 => config.gamepad.deadzone = step_tenths(config.gamepad.deadzone, MAX_DEADZONE),
+            16 => config.effects.enabled = !config.effects.enabled,
+            17 => config.effects.screen_shake = !config.effects.screen_shake,
+            18 => config.ai.difficulty = config.ai.difficulty.next(),
+            19 => config.ai.hints = !config.ai.hints,
+            _ => (),
+        }
+        self.sounds.set_volumes(&self.config.audio);
+        self.effects.set_config(&self.config.effects);
+    }
+
+    pub fn draw_settings(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let list = SETTINGS
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let cursor = if index == self.selected_setting {
+                    ">"
+                } else {
+                    " "
+                };
+                format!("{} {:<18} {}", cursor, name, self.setting_value(index))
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let text = format!(
+            "Settings (Up/Down to choose, Left/Right to change, Escape to save and go back)\n\n{}\n\nControls and everything else can be changed in {}",
+            list,
+            self.config_path.display()
+        );
+        self.style
+            .text(text)
+            .draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
+    }
+
+    pub fn show_controls(&mut self) {
+        self.selected_action = 0;
+        self.binding = false;
+        self.screen = Screen::Controls;
+    }
+
+    pub fn draw_controls(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let bindings = &self.config.bindings;
+        let buttons = &self.config.gamepad.buttons;
+        let names = |names: &Vec<String>| {
+            if names.is_empty() {
+                String::from("(none)")
+            } else {
+                names.join(", ")
             }
         };
+        let list = ACTIONS
+            .iter()
+            .enumerate()
+            .map(|(index, action)| {
+                let cursor = if index == self.selected_action {
+                    ">"
+                } else {
+                    " "
+                };
+                format!(
+                    "{} {:<24} {:<20} {}",
+                    cursor,
+                    action.name(),
+                    names(bindings.keys(*action)),
+                    names(buttons.keys(*action))
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
 
-        tetromino.blocks().iter().for_each(|block| {
-            if self.grid.check_occupied(
-                (tetromino.position.x + block.position.x) as u32,
-                (tetromino.position.y + block.position.y) as u32,
-            ) {
-                next_pos_empty = false;
+        let mut text = format!(
+            "Controls (Up/Down to choose, Enter to add a key or button, Backspace to clear, Escape to go back)\n\n  {:<24} {:<20} {}\n{}",
+            "Action",
+            "Keys",
+            "Buttons",
+            list
+        );
+        let mut conflicts = bindings.conflicts();
+        conflicts.append(&mut buttons.conflicts());
+        conflicts.append(&mut bindings.conflicts_with(&self.config.versus.bindings));
+        if !conflicts.is_empty() {
+            text = format!("{}\n\nWarning:\n{}", text, conflicts.join("\n"));
+        }
+        if self.binding {
+            text = format!(
+                "{}\n\nPress a key or button for {} (Escape to cancel)",
+                text,
+                ACTIONS[self.selected_action].name()
+            );
+        }
+        self.style
+            .text(text)
+            .draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
+    }
+
+    //Inputs being made by player 1, or by player 2 in a versus game. Players count from 0.
+    fn player_input(&mut self, player: usize) -> Option<&mut FrameInput> {
+        match (player, self.rival.as_mut()) {
+            (0, _) => Some(&mut self.input),
+            (_, Some(rival)) => Some(&mut rival.input),
+            _ => None,
+        }
+    }
+
+    //Starts an action of a player in a game, from a key, a button or a stick.
+    fn action_down(&mut self, ctx: &mut Context, player: usize, action: Action) {
+        match action {
+            Action::Quit => self.quit(ctx),
+            Action::Pause => {
+                self.paused = !self.paused;
+                if self.paused {
+                    self.sounds.pause_music();
+                } else {
+                    self.sounds.resume_music();
+                }
             }
-        });
+            _ if self.paused => (),
+            _ => {
+                if let (Some(input), Some(frame_input)) =
+                    (action.input(), self.player_input(player))
+                {
+                    frame_input.held |= input;
+                    frame_input.press(input);
+                }
+            }
+        }
+    }
+
+    fn action_up(&mut self, player: usize, action: Action) {
+        if let (Some(input), Some(frame_input)) = (action.input(), self.player_input(player)) {
+            frame_input.held &= !input;
+        }
+    }
+
+    //What a key does for each player. Player 2 only has keys in a versus game.
+    fn key_actions(&self, keycode: KeyCode) -> Vec<(usize, Action)> {
+        let name = match key_name(keycode) {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+        let mut actions = Vec::new();
+        if let Some(action) = self.config.bindings.action(name) {
+            actions.push((0, action));
+        }
+        if self.rival.is_some() {
+            if let Some(action) = self.config.versus.bindings.action(name) {
+                actions.push((1, action));
+            }
+        }
+        actions
+    }
 
-        if next_pos_empty && self.to_lock {
-            self.to_lock = false;
-            self.lock_timer = Duration::from_millis(0);
+    //The player a gamepad plays for. Outside of versus games every gamepad plays for player 1.
+    fn gamepad_player(&mut self, id: GamepadId) -> usize {
+        if !self.gamepads.contains(&id) {
+            self.gamepads.push(id);
+        }
+        match self.rival {
+            Some(_) if self.gamepads[0] != id => 1,
+            _ => 0,
         }
+    }
+
+    fn button_action(&self, button: Button) -> Option<Action> {
+        button_name(button).and_then(|name| self.config.gamepad.buttons.action(name))
+    }
+
+    //Shown instead of the grid while the game is paused, so it can't be studied without the clock
+    //running.
+    pub fn draw_paused(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.draw_score(ctx, &self.engine)?;
+        let keys = self.config.bindings.keys(Action::Pause).join(", ");
+        let text = self
+            .style
+            .text(format!("Paused\n\nPress {} to continue.", keys));
+        let center = self.layout(ctx, &self.engine).grid_center();
+        text.draw(
+            ctx,
+            DrawParam::new().dest(Point2::new(center.x - 80.0, center.y)),
+        )
+    }
 
-        match next_pos_empty {
-            true => Some(tetromino),
-            false => None,
+    //Picks up the game that was saved when the player last quit.
+    pub fn resume_game(&mut self) {
+        match load_game(&self.save_path, &self.piece_sets) {
+            Ok((engine, replay)) => {
+                self.engine = engine;
+                self.replay = replay;
+                self.input = FrameInput::default();
+                self.paused = false;
+                self.screen = Screen::Playing;
+                self.effects.reset();
+                self.sounds.start_music(self.engine.stage());
+                if let Err(e) = fs::remove_file(&self.save_path) {
+                    println!("Couldn't remove the saved game: {}", e);
+                }
+            }
+            Err(e) => self.last_result = Some(format!("Couldn't resume the saved game: {}", e)),
         }
     }
 
-    //Moves our tetromino to the its new position.
-    pub fn move_tetromino(&mut self, direction: &MoveDirection) -> GameResult<()> {
-        //Check if incoming move is valid. If yes, swap current tetromino with new one. If not,
-        //keep current tetromino
-        self.tetromino = self.validate_move(direction).or(self.tetromino);
+    //Quits, saving the game first if one is being played.
+    pub fn quit(&mut self, ctx: &mut Context) {
+        self.save_game();
+        ggez::event::quit(ctx);
+    }
 
-        //Check if tetromino reached the lowest point of our grid. If yes, lock it up and generate
-        //a new one.
-        if self.validate_move(&MoveDirection::Down).is_none() && self.to_lock == false {
-            self.to_lock = true;
+    fn save_game(&mut self) {
+        if self.screen != Screen::Playing || self.bot.is_some() || self.rival.is_some() {
+            return;
+        }
+        if let Err(e) = save_game(&self.save_path, &self.engine, &self.replay) {
+            println!("Couldn't save the game: {}", e);
         }
+    }
 
-        Ok(())
+    //Shows the whole stack of the game that just ended.
+    pub fn draw_reveal(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.draw_grid(ctx, &self.engine)?;
+        if let Some(rival) = &self.rival {
+            self.draw_grid(ctx, &rival.engine)?;
+        }
+        let text = self.style.text(format!(
+            "{}\n\nPress any key to continue.",
+            self.last_result.as_ref().unwrap()
+        ));
+        let score = self.layout(ctx, &self.engine).score;
+        text.draw(ctx, DrawParam::new().dest(score))
     }
 
-    //Locks tetromino on the grid.
-    pub fn lock_tetromino(&mut self) {
-        self.tetromino
-            .as_ref()
-            .unwrap()
-            .blocks()
-            .iter()
-            .for_each(|block| {
-                let abs_block_x = self.tetromino.as_ref().unwrap().position.x + block.position.x;
-                let abs_block_y = self.tetromino.as_ref().unwrap().position.y + block.position.y;
-
-                self.grid.blocks[abs_block_x as usize][abs_block_y as usize] = Block {
-                    position: Point2::new(abs_block_x, abs_block_y),
-                    color: block.color,
-                    state: BlockState::Filled,
-                };
-            });
+    //Opens the replay browser.
+    pub fn show_replays(&mut self) {
+        self.replays = list_replays(&self.replay_dir);
+        self.selected_replay = 0;
+        self.screen = Screen::Replays;
     }
 
-    //Draws the grid for our game.
-    pub fn draw_grid(&self, ctx: &mut Context) -> GameResult<()> {
-        let mut grid = graphics::MeshBuilder::new();
-        self.grid.blocks.iter().for_each(|x| {
-            x.iter().for_each(|y| {
-                match y.state {
-                    BlockState::Filled => {
-                        grid.rectangle(
-                            DrawMode::fill(),
-                            Rect::new(
-                                y.position.x * BLOCK_WIDTH,
-                                y.position.y * BLOCK_HEIGHT,
-                                BLOCK_WIDTH,
-                                BLOCK_HEIGHT,
-                            ),
-                            y.color,
-                        );
-                        //Draw outline
-                        grid.rectangle(
-                            DrawMode::stroke(2.0),
-                            Rect::new(
-                                y.position.x * BLOCK_WIDTH,
-                                y.position.y * BLOCK_HEIGHT,
-                                BLOCK_WIDTH,
-                                BLOCK_HEIGHT,
-                            ),
-                            Color::new(0.5, 0.5, 0.5, 1.0),
-                        );
-                    }
-                    BlockState::Empty => {}
+    pub fn draw_replays(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let first = self
+            .selected_replay
+            .saturating_sub(REPLAYS_SHOWN / 2)
+            .min(self.replays.len().saturating_sub(REPLAYS_SHOWN));
+        let list = self
+            .replays
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(REPLAYS_SHOWN)
+            .map(|(index, (path, replay))| {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                let cursor = if index == self.selected_replay {
+                    ">"
+                } else {
+                    " "
                 };
+                match replay {
+                    Ok(replay) => {
+                        format!("{} {}  {:?}  {}", cursor, name, replay.mode, replay.summary)
+                    }
+                    Err(e) => format!("{} {}  can't be played: {}", cursor, name, e),
+                }
             })
-        });
+            .collect::<Vec<String>>()
+            .join("\n");
 
-        grid.rectangle(
-            DrawMode::stroke(2.0),
-            Rect::new(
-                0.0,
-                0.0,
-                GRID_WIDTH as f32 * BLOCK_WIDTH,
-                GRID_HEIGHT as f32 * BLOCK_HEIGHT,
-            ),
-            Color::new(0.5, 0.5, 0.5, 1.0),
-        );
+        let text = if self.replays.is_empty() {
+            String::from("No replays yet.\n\nPress Escape to go back.")
+        } else {
+            format!(
+                "Replays (Up/Down to choose, Enter to watch, Escape to go back)\n\n{}",
+                list
+            )
+        };
+        self.style
+            .text(text)
+            .draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))
+    }
 
-        let d_param = DrawParam::default().dest(Point2::new(
-            (graphics::size(&ctx).0 / 2.0) - (BLOCK_WIDTH * GRID_WIDTH as f32) as f32 / 2.0,
-            20.0,
+    //Starts playing back the selected replay.
+    pub fn watch_replay(&mut self) {
+        let replay = match self.replays.get(self.selected_replay) {
+            Some((_, Ok(replay))) => replay.clone(),
+            _ => return,
+        };
+        match Playback::new(replay, &self.piece_sets) {
+            Ok(playback) => {
+                self.playback = Some(playback);
+                self.playback_error = None;
+                self.screen = Screen::Playback;
+            }
+            Err(e) => self.replays[self.selected_replay].1 = Err(e),
+        }
+    }
+
+    pub fn draw_playback(&self, ctx: &mut Context) -> GameResult<()> {
+        let playback = self.playback.as_ref().unwrap();
+        self.draw_game(ctx, &playback.engine)?;
+
+        let state = if playback.is_finished() {
+            " (finished)"
+        } else if playback.paused {
+            " (paused)"
+        } else {
+            ""
+        };
+        let error = match &self.playback_error {
+            Some(e) => format!("\nCan't seek: {}", e),
+            None => String::new(),
+        };
+        let text = self.style.text(format!(
+            "Replay {} / {}  x{}{}\nSpace to pause, Left/Right to seek,\nUp/Down to change speed{}",
+            format_time(playback.position()),
+            format_time(playback.replay.frames()),
+            playback.speed(),
+            state,
+            error
         ));
+        let footer = self.layout(ctx, &playback.engine).footer;
+        text.draw(ctx, DrawParam::new().dest(footer))
+    }
 
-        let mesh = grid.build(ctx).unwrap();
-        graphics::draw(ctx, &mesh, d_param)
+    //Size of a block on the canvas the game is laid out on, in pixels.
+    fn block_size(&self) -> f32 {
+        self.config.display.block_size as f32
     }
 
-    ///Draws our tetromino in the grid on its current position.
-    pub fn draw_tetromino(&self, ctx: &mut Context) -> GameResult<()> {
-        let mut tetromino = graphics::MeshBuilder::new();
-        let tet = self.tetromino.as_ref().unwrap();
-        tet.blocks().iter().for_each(|x| {
-            tetromino.rectangle(
-                DrawMode::fill(),
-                Rect::new(
-                    (tet.position.x + x.position.x) * BLOCK_WIDTH,
-                    (tet.position.y + x.position.y) * BLOCK_HEIGHT,
-                    BLOCK_WIDTH,
-                    BLOCK_HEIGHT,
-                ),
-                x.color,
-            );
-            tetromino.rectangle(
-                DrawMode::stroke(2.0),
-                Rect::new(
-                    (tet.position.x + x.position.x) * BLOCK_WIDTH,
-                    (tet.position.y + x.position.y) * BLOCK_HEIGHT,
-                    BLOCK_WIDTH,
-                    BLOCK_HEIGHT,
-                ),
-                Color::new(0.5, 0.5, 0.5, 1.0),
-            );
-        });
+    //Where the parts of a game are drawn in the window as it is now.
+    fn layout(&self, ctx: &Context, engine: &Engine) -> Layout {
+        match self.player(engine) {
+            Some(player) => Layout::versus(
+                graphics::size(ctx),
+                self.config.display.orientation,
+                self.block_size(),
+                engine.grid.width,
+                engine.options.height,
+                player,
+            ),
+            None => Layout::new(
+                graphics::size(ctx),
+                self.config.display.orientation,
+                self.block_size(),
+                engine.grid.width,
+                engine.options.height,
+            ),
+        }
+    }
 
-        let d_param = DrawParam::default().dest(Point2::new(
-            (graphics::size(&ctx).0 / 2.0) - (BLOCK_WIDTH * GRID_WIDTH as f32) as f32 / 2.0,
-            20.0,
-        ));
-        let mesh = tetromino.build(ctx).unwrap();
-        graphics::draw(ctx, &mesh, d_param)
+    //Which player of a versus game plays `engine`, counting from 0. None outside of versus games.
+    fn player(&self, engine: &Engine) -> Option<usize> {
+        match &self.rival {
+            Some(rival) if std::ptr::eq(engine, &rival.engine) => Some(1),
+            Some(_) => Some(0),
+            None => None,
+        }
     }
 
-    pub fn draw_next_tetromino(&self, ctx: &mut Context) -> GameResult<()> {
-        let mut tetromino = graphics::MeshBuilder::new();
-        let tet = self.tetromino_next.as_ref().unwrap();
+    //Where the top left corner of the grid, hidden rows included, is drawn.
+    fn grid_origin(&self, ctx: &Context, engine: &Engine) -> Point2<f32> {
+        let grid = self.layout(ctx, engine).grid;
+        let (shake_x, shake_y) = self.effects.offset();
+        Point2::new(
+            grid.x + shake_x,
+            grid.y - engine.options.hidden_rows as f32 * self.block_size() + shake_y,
+        )
+    }
 
-        tet.blocks().iter().for_each(|x| {
-            tetromino.rectangle(
+    //Draws everything that is shown while a game is played.
+    fn draw_game(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        self.draw_score(ctx, engine)?;
+        self.draw_grid(ctx, engine)?;
+        if self.screen == Screen::Playing && self.shows_hints() {
+            self.draw_hint(ctx, engine)?;
+        }
+        if let Some(target) = engine.finesse.target.as_ref() {
+            self.draw_dashed(ctx, engine, target)?;
+        }
+        //Effects are only kept for player 1.
+        if self.player(engine) != Some(1) {
+            let origin = self.grid_origin(ctx, engine);
+            self.effects
+                .draw(ctx, engine, &self.style, origin, self.block_size())?;
+        }
+        self.draw_tetromino(ctx, engine)?;
+        self.draw_next_tetromino(ctx, engine)?;
+        self.draw_hold_tetromino(ctx, engine)?;
+        self.draw_garbage_meter(ctx, engine)?;
+        if let (Some(bot), Screen::Playing) = (&self.bot, self.screen) {
+            let text = self.style.text(format!("{} playing", bot.name()));
+            let footer = self.layout(ctx, engine).footer;
+            text.draw(ctx, DrawParam::new().dest(footer))?;
+        }
+        if let Some(player) = self.player(engine) {
+            let text = self.style.text(format!("Player {}", player + 1));
+            let footer = self.layout(ctx, engine).footer;
+            text.draw(ctx, DrawParam::new().dest(footer))?;
+        }
+        Ok(())
+    }
+
+    //Hints are only given to a player playing on their own.
+    fn shows_hints(&self) -> bool {
+        self.config.ai.hints && self.bot.is_none() && self.rival.is_none()
+    }
+
+    //Draws a bar left of the grid, as tall as the rows of garbage waiting to be raised.
+    fn draw_garbage_meter(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        let rows = engine.garbage.pending_rows();
+        if rows == 0 {
+            return Ok(());
+        }
+        let layout = self.layout(ctx, engine);
+        let size = self.block_size();
+        let height = (rows as f32 * size).min(layout.grid_height);
+        let (shake_x, shake_y) = self.effects.offset();
+        let mesh = graphics::MeshBuilder::new()
+            .rectangle(
                 DrawMode::fill(),
                 Rect::new(
-                    (2.0 + x.position.x) * BLOCK_WIDTH,
-                    (2.0 + x.position.y) * BLOCK_HEIGHT,
-                    BLOCK_WIDTH,
-                    BLOCK_HEIGHT,
+                    layout.grid.x - size / 2.0 + shake_x,
+                    layout.grid.y + layout.grid_height - height + shake_y,
+                    size / 3.0,
+                    height,
                 ),
-                x.color,
-            );
-            tetromino.rectangle(
-                DrawMode::stroke(2.0),
-                Rect::new(
-                    (2.0 + x.position.x) * BLOCK_WIDTH,
-                    (2.0 + x.position.y) * BLOCK_HEIGHT,
-                    BLOCK_WIDTH,
-                    BLOCK_HEIGHT,
-                ),
-                Color::new(0.5, 0.5, 0.5, 1.0),
-            );
+                Color::new(0.9, 0.2, 0.2, 1.0),
+            )
+            .build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    //Draws the grid for our game.
+    pub fn draw_grid(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        let mut grid = self.style.blocks(&engine.pieces, self.block_size());
+        let size = self.block_size();
+        let hidden_rows = engine.options.hidden_rows as f32;
+        let clearing_rows = self.effects.clearing_rows(engine);
+        engine.grid.blocks.iter().for_each(|x| {
+            x.iter().for_each(|y| {
+                if y.state != BlockState::Filled
+                    || y.position.y < hidden_rows
+                    || clearing_rows.contains(&(y.position.y as u32))
+                {
+                    return;
+                }
+                match engine.block_look(y) {
+                    BlockLook::Solid(alpha) => grid.add(y.position.x, y.position.y, y, alpha),
+                    BlockLook::Outline => grid.add_outline(y.position.x, y.position.y, y),
+                    BlockLook::Hidden => {}
+                };
+            })
         });
 
-        let d_param = DrawParam::default().dest(Point2::new(
-            ((graphics::size(&ctx).0 / 2.0) + (BLOCK_WIDTH * GRID_WIDTH as f32) as f32 / 2.0)
-                + 20.0,
-            20.0,
+        grid.add_frame(Rect::new(
+            0.0,
+            hidden_rows * size,
+            engine.grid.width as f32 * size,
+            engine.options.height as f32 * size,
         ));
-        let mesh = tetromino.build(ctx).unwrap();
-        graphics::draw(ctx, &mesh, d_param)
+
+        let origin = self.grid_origin(ctx, engine);
+        grid.draw(ctx, origin)
     }
 
-    fn update_score(&mut self, clears: &LineClears) {
-        //Update the score
-        match clears {
-            LineClears::NoClear => {}
-            LineClears::Single => {
-                self.single += 1;
-                self.score += 1000;
-            }
-            LineClears::Double => {
-                self.double += 1;
-                self.score += 4000;
-            }
-            LineClears::Tripple => {
-                self.tripple += 1;
-                self.score += 6000;
+    //Works out where the AI would put the tetromino in play, when a new one comes into play.
+    fn update_hint(&mut self) {
+        let key = match self.engine.tetromino {
+            Some(tetromino) => (self.engine.pieces_locked, tetromino.piece),
+            None => return,
+        };
+        match self.hint {
+            Some((hint_key, _)) if hint_key == key => (),
+            _ => {
+                let placement = best_placement(&self.engine, &Weights::default(), false);
+                self.hint = Some((key, placement));
             }
-            LineClears::Rustris => {
-                self.rustris += 1;
-                self.score += 12000;
+        }
+    }
+
+    //Draws a dashed outline where the AI would put the tetromino in play.
+    pub fn draw_hint(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        match self.hint {
+            Some((_, Some(placement))) => self.draw_dashed(ctx, engine, &placement.tetromino),
+            _ => Ok(()),
+        }
+    }
+
+    //Draws a dashed outline of a tetromino on the grid, leaving out the hidden rows.
+    fn draw_dashed(
+        &self,
+        ctx: &mut Context,
+        engine: &Engine,
+        tetromino: &Tetromino,
+    ) -> GameResult<()> {
+        let hidden_rows = engine.options.hidden_rows as i32;
+        let cells = cells(&engine.pieces, tetromino)
+            .into_iter()
+            .filter(|(_, y)| *y >= hidden_rows)
+            .collect::<Vec<_>>();
+        let block = match tetromino.blocks(&engine.pieces).first() {
+            Some(block) => *block,
+            None => return Ok(()),
+        };
+        let mut hint = self.style.blocks(&engine.pieces, self.block_size());
+        hint.add_dashed_outline(&cells, &block)?;
+        let origin = self.grid_origin(ctx, engine);
+        hint.draw(ctx, origin)
+    }
+
+    ///Draws our tetromino in the grid on its current position.
+    pub fn draw_tetromino(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        let mut tetromino = self.style.blocks(&engine.pieces, self.block_size());
+        let tet = match engine.tetromino.as_ref() {
+            Some(tet) => tet,
+            None => return Ok(()),
+        };
+        let hidden_rows = engine.options.hidden_rows as f32;
+        tet.blocks(&engine.pieces)
+            .iter()
+            .filter(|x| tet.position.y + x.position.y >= hidden_rows)
+            .for_each(|x| {
+                tetromino.add(
+                    tet.position.x + x.position.x,
+                    tet.position.y + x.position.y,
+                    x,
+                    1.0,
+                );
+            });
+
+        let origin = self.grid_origin(ctx, engine);
+        tetromino.draw(ctx, origin)
+    }
+
+    pub fn draw_next_tetromino(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        let tet = engine.tetromino_next.as_ref().unwrap();
+        let position = self.layout(ctx, engine).next;
+        self.draw_preview(ctx, engine, tet, position)
+    }
+
+    pub fn draw_hold_tetromino(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        match engine.tetromino_hold.as_ref() {
+            Some(tet) => {
+                let position = self.layout(ctx, engine).hold;
+                self.draw_preview(ctx, engine, tet, position)
             }
+            None => Ok(()),
         }
     }
 
-    fn draw_score(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let info_text = graphics::Text::new(format!(
-            "Score: {}\n\nLevel: {}\n\nLine Clears: \n  Single: {}\n  Double: {}\n  Tripple: {}\n  Rustris: {}",
-            self.score, self.get_level(), self.single, self.double, self.tripple, self.rustris
-        ));
-        info_text.draw(ctx, DrawParam::new().dest(Point2::new(30.0, 30.0)))?;
+    //Draws a tetromino in a box whose top left corner is at `position`. Big tetrominoes are drawn
+    //at their normal size so they fit in it.
+    fn draw_preview(
+        &self,
+        ctx: &mut Context,
+        engine: &Engine,
+        tet: &Tetromino,
+        position: Point2<f32>,
+    ) -> GameResult<()> {
+        let size = self.block_size();
+        let mut tetromino = self.style.blocks(&engine.pieces, size);
+        let tet = Tetromino { scale: 1, ..*tet };
+
+        tet.blocks(&engine.pieces).iter().for_each(|x| {
+            tetromino.add(2.0 + x.position.x, 2.0 + x.position.y, x, 1.0);
+        });
+
+        let (shake_x, shake_y) = self.effects.offset();
+        let origin = Point2::new(position.x + shake_x, position.y + shake_y);
+        tetromino.draw(ctx, origin)
+    }
+
+    fn draw_score(&self, ctx: &mut Context, engine: &Engine) -> GameResult<()> {
+        let layout = self.layout(ctx, engine);
+        //There is only room for a few lines under the grid in portrait layouts.
+        let info_text = match (engine.mode, layout.orientation) {
+            (GameMode::Marathon, Orientation::Portrait) => self.style.text(format!(
+                "Score: {}  Level: {}\nSingle: {}  Double: {}\nTripple: {}  Rustris: {}  5+ lines: {}\nFinesse faults: {}",
+                engine.score, engine.get_level(), engine.single, engine.double, engine.tripple, engine.rustris, engine.more_lines, engine.finesse.faults
+            )),
+            (GameMode::Marathon, _) => self.style.text(format!(
+                "Score: {}\n\nLevel: {}\n\nLine Clears: \n  Single: {}\n  Double: {}\n  Tripple: {}\n  Rustris: {}\n  5+ lines: {}\n\nFinesse faults: {}",
+                engine.score, engine.get_level(), engine.single, engine.double, engine.tripple, engine.rustris, engine.more_lines, engine.finesse.faults
+            )),
+            (GameMode::Drill, Orientation::Portrait) => self.style.text(format!(
+                "Targets hit: {} / {}  Faults: {}\n{}",
+                engine.finesse.targets_hit,
+                DRILL_PIECES,
+                engine.finesse.faults,
+                engine.finesse.last.as_ref().map_or_else(String::new, Judgement::describe)
+            )),
+            (GameMode::Drill, _) => self.style.text(format!(
+                "Drill\n\nTargets hit: {} / {}\n\nFinesse faults: {}\n\nTime: {}\n\n{}",
+                engine.finesse.targets_hit,
+                DRILL_PIECES,
+                engine.finesse.faults,
+                format_time(engine.frame),
+                engine.finesse.last.as_ref().map_or_else(String::new, Judgement::describe)
+            )),
+            (GameMode::Master, Orientation::Portrait) => self.style.text(format!(
+                "Grade: {}  Score: {}\nLevel: {:03} / {:03}\nTime: {}",
+                engine.master.grade(),
+                engine.master.score,
+                engine.master.level,
+                engine.master.level_stop(),
+                format_time(engine.frame)
+            )),
+            (GameMode::Master, _) => {
+                let sections = engine
+                    .master
+                    .section_times
+                    .iter()
+                    .enumerate()
+                    .map(|(index, time)| format!("  {:03}: {}", index * 100, format_time(*time)))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                self.style.text(format!(
+                    "Grade: {}\n\nScore: {}\n\nLevel: {:03}\n       {:03}\n\nTime: {}\n\nSections:\n{}",
+                    engine.master.grade(),
+                    engine.master.score,
+                    engine.master.level,
+                    engine.master.level_stop(),
+                    format_time(engine.frame),
+                    sections
+                ))
+            }
+        };
+        info_text.draw(ctx, DrawParam::new().dest(layout.score))?;
 
         Ok(())
     }
@@ -353,54 +1218,80 @@ impl Rustris {
 
 impl EventHandler for Rustris {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if self.last_update.elapsed() >= Duration::from_millis(MILLIS_PER_UPDATE) {
-            if !self.game_running {
-                if input::keyboard::pressed_keys(ctx).len() > 0 {
-                    self.grid.reset();
-                    self.game_running = true;
-                    self.generate_tetromino();
-                }
-            } else {
-                self.move_tetromino_down = self.move_tetromino_down.add(self.last_update.elapsed());
-                if self.to_lock == true {
-                    self.lock_timer = self.lock_timer.add(self.last_update.elapsed());
-                }
-
-                if self.lock_timer >= Duration::from_millis(self.get_move_delay() as u64)
-                    && self.to_lock == true
-                {
-                    self.to_lock = false;
-                    self.lock_timer = Duration::new(0, 0);
-                    self.lock_tetromino();
-                    self.generate_tetromino();
-                } else {
-                    if self.move_tetromino_down.as_millis() >= self.get_move_delay() as u128 {
-                        self.move_tetromino(&MoveDirection::Down)?;
-                        self.move_tetromino_down = Duration::from_millis(0);
+        while timer::check_update_time(ctx, FRAMES_PER_SECOND) {
+            match self.screen {
+                Screen::Playing if !self.paused => {
+                    //The player's own inputs are ignored while a bot plays.
+                    let input = match self.bot.as_mut() {
+                        Some(bot) => bot.next_input(&self.engine),
+                        None => self.input,
+                    };
+                    self.input.pressed = 0;
+                    if self.rival.is_none() {
+                        self.replay.record(input);
+                    }
+                    self.engine.tick(input);
+                    self.coach.update(&self.engine);
+                    if self.shows_hints() {
+                        self.update_hint();
+                    }
+                    self.sounds
+                        .play_events(self.engine.events(), self.engine.stage());
+                    let mut rival_over = false;
+                    if let Some(rival) = self.rival.as_mut() {
+                        let input = rival.input;
+                        rival.input.pressed = 0;
+                        rival.engine.tick(input);
+                        exchange_garbage(&mut self.engine, &mut rival.engine);
+                        self.sounds
+                            .play_events(rival.engine.events(), rival.engine.stage());
+                        rival_over = rival.engine.is_game_over();
+                    }
+                    self.effects.start(&self.engine, self.block_size());
+                    self.effects.update();
+                    if self.engine.is_game_over() || rival_over {
+                        self.end_game();
                     }
                 }
+                Screen::Playback => self.playback.as_mut().unwrap().update(),
+                _ => (),
             }
-            self.last_update = Instant::now();
         }
 
-        if self.lock_timer != Duration::new(0, 0) {}
-        //Clear filled lines and get the number of lines cleared back.
-        let cleared = self.grid.clear_lines();
-        self.update_score(&cleared);
-
         GameResult::Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx, graphics::BLACK);
+        graphics::clear(ctx, Color::from(self.style.theme.background));
 
-        if !self.game_running {
-            self.draw_intro(ctx).unwrap();
-        } else {
-            self.draw_score(ctx).unwrap();
-            self.draw_grid(ctx).unwrap();
-            self.draw_tetromino(ctx)?;
-            self.draw_next_tetromino(ctx)?;
+        //Games are scaled to fit the window, menus are drawn at the size of the window.
+        let (width, height) = graphics::size(ctx);
+        let screen = match self.screen {
+            Screen::Playing | Screen::Reveal => self.layout(ctx, &self.engine).screen,
+            Screen::Playback => {
+                self.layout(ctx, &self.playback.as_ref().unwrap().engine)
+                    .screen
+            }
+            _ => Rect::new(0.0, 0.0, width, height),
+        };
+        graphics::set_screen_coordinates(ctx, screen)?;
+
+        match self.screen {
+            Screen::Intro => self.draw_intro(ctx)?,
+            Screen::Playing if self.paused => self.draw_paused(ctx)?,
+            Screen::Playing => {
+                self.draw_game(ctx, &self.engine)?;
+                if let Some(rival) = &self.rival {
+                    self.draw_game(ctx, &rival.engine)?;
+                }
+            }
+            Screen::Reveal => self.draw_reveal(ctx)?,
+            Screen::Replays => self.draw_replays(ctx)?,
+            Screen::Playback => self.draw_playback(ctx)?,
+            Screen::NameEntry => self.draw_name_entry(ctx)?,
+            Screen::HighScores => self.draw_high_scores(ctx)?,
+            Screen::Settings => self.draw_settings(ctx)?,
+            Screen::Controls => self.draw_controls(ctx)?,
         }
         graphics::present(ctx)?;
         timer::yield_now();
@@ -414,29 +1305,244 @@ impl EventHandler for Rustris {
         _keymods: KeyMods,
         repeat: bool,
     ) {
-        if self.game_running {
-            //Todo Make this more ... Professional?
-            self.lock_timer = Duration::from_millis(0);
-            match keycode {
-                KeyCode::Up => {
+        match self.screen {
+            Screen::Playing => {
+                for (player, action) in self.key_actions(keycode) {
                     if !repeat {
-                        self.move_tetromino(&MoveDirection::Up).unwrap();
+                        self.action_down(ctx, player, action);
                     }
                 }
+            }
+            Screen::Reveal => match keycode {
+                KeyCode::Escape => ggez::event::quit(ctx),
+                _ if !repeat => self.after_game(),
+                _ => (),
+            },
+            Screen::Replays => match keycode {
+                KeyCode::Escape => self.screen = Screen::Intro,
+                KeyCode::Up => self.selected_replay = self.selected_replay.saturating_sub(1),
                 KeyCode::Down => {
-                    self.move_tetromino(&MoveDirection::Down).unwrap();
+                    self.selected_replay =
+                        (self.selected_replay + 1).min(self.replays.len().saturating_sub(1));
                 }
-                KeyCode::Left => {
-                    self.move_tetromino(&MoveDirection::Left).unwrap();
+                KeyCode::Return => self.watch_replay(),
+                _ => (),
+            },
+            Screen::Playback => {
+                let playback = self.playback.as_mut().unwrap();
+                match keycode {
+                    KeyCode::Escape => {
+                        self.playback = None;
+                        self.screen = Screen::Replays;
+                    }
+                    KeyCode::Space => playback.paused = !playback.paused,
+                    KeyCode::Left => {
+                        let frame = playback.position().saturating_sub(SEEK_FRAMES);
+                        self.playback_error = playback.seek(frame).err();
+                    }
+                    KeyCode::Right => {
+                        let frame = playback.position() + SEEK_FRAMES;
+                        self.playback_error = playback.seek(frame).err();
+                    }
+                    KeyCode::Up => playback.faster(),
+                    KeyCode::Down => playback.slower(),
+                    _ => (),
                 }
-                KeyCode::Right => {
-                    self.move_tetromino(&MoveDirection::Right).unwrap();
+            }
+            Screen::NameEntry => match keycode {
+                KeyCode::Back => {
+                    self.new_score.as_mut().unwrap().name.pop();
+                }
+                KeyCode::Return => self.submit_score(),
+                _ => (),
+            },
+            Screen::HighScores => match keycode {
+                KeyCode::Left | KeyCode::Right => {
+                    self.scores_mode = match self.scores_mode {
+                        GameMode::Marathon => GameMode::Master,
+                        GameMode::Master | GameMode::Drill => GameMode::Marathon,
+                    };
+                    self.highlighted_score = None;
                 }
+                KeyCode::Escape | KeyCode::Return => self.screen = Screen::Intro,
+                _ => (),
+            },
+            Screen::Settings => match keycode {
+                KeyCode::Escape => self.close_settings(ctx),
+                KeyCode::Return if SETTINGS[self.selected_setting] == "Controls" => {
+                    self.show_controls()
+                }
+                KeyCode::Up => self.selected_setting = self.selected_setting.saturating_sub(1),
+                KeyCode::Down => {
+                    self.selected_setting = (self.selected_setting + 1).min(SETTINGS.len() - 1);
+                }
+                KeyCode::Left => self.adjust_setting(ctx, false),
+                KeyCode::Right => self.adjust_setting(ctx, true),
+                _ => (),
+            },
+            Screen::Controls if self.binding => {
+                if !repeat {
+                    self.binding = false;
+                    match key_name(keycode) {
+                        Some(name) if keycode != KeyCode::Escape => {
+                            let action = ACTIONS[self.selected_action];
+                            self.config.bindings.bind(action, name);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Screen::Controls => match keycode {
                 KeyCode::Escape => {
-                    ggez::event::quit(ctx);
+                    self.save_config();
+                    self.screen = Screen::Settings;
+                }
+                KeyCode::Up => self.selected_action = self.selected_action.saturating_sub(1),
+                KeyCode::Down => {
+                    self.selected_action = (self.selected_action + 1).min(ACTIONS.len() - 1);
+                }
+                KeyCode::Return => self.binding = true,
+                KeyCode::Back | KeyCode::Delete => {
+                    let action = ACTIONS[self.selected_action];
+                    self.config.bindings.keys_mut(action).clear();
+                    self.config.gamepad.buttons.keys_mut(action).clear();
                 }
                 _ => (),
+            },
+            Screen::Intro => match keycode {
+                KeyCode::Escape => ggez::event::quit(ctx),
+                KeyCode::Left => {
+                    self.config.rules.width = (self.config.rules.width - 1).max(MIN_GRID_WIDTH);
+                }
+                KeyCode::Right => {
+                    self.config.rules.width = (self.config.rules.width + 1).min(MAX_GRID_WIDTH);
+                }
+                KeyCode::Down => {
+                    self.config.rules.height = (self.config.rules.height - 1).max(MIN_GRID_HEIGHT);
+                }
+                KeyCode::Up => {
+                    self.config.rules.height = (self.config.rules.height + 1).min(MAX_GRID_HEIGHT);
+                }
+                KeyCode::B => {
+                    self.config.rules.big = !self.config.rules.big;
+                }
+                KeyCode::S => {
+                    self.config.rules.stack = self.config.rules.stack.next();
+                }
+                KeyCode::P => {
+                    self.piece_set = (self.piece_set + 1) % self.piece_sets.len();
+                }
+                KeyCode::R => self.show_replays(),
+                KeyCode::H => self.show_high_scores(),
+                KeyCode::O => self.show_settings(),
+                KeyCode::L if self.save_path.exists() => self.resume_game(),
+                KeyCode::M => self.start_game(GameMode::Master),
+                KeyCode::F => self.start_game(GameMode::Drill),
+                KeyCode::V => self.start_versus(),
+                KeyCode::A => {
+                    let bot = AiBot::new(self.config.ai.difficulty);
+                    self.start_bot_game(Box::new(bot));
+                }
+                KeyCode::T if !self.config.ai.tbp_bot.is_empty() => self.start_tbp_game(),
+                _ => self.start_game(GameMode::Marathon),
+            },
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if self.screen != Screen::NameEntry {
+            return;
+        }
+        let entry = self.new_score.as_mut().unwrap();
+        if !character.is_control() && entry.name.chars().count() < MAX_NAME_LENGTH {
+            entry.name.push(character);
+        }
+    }
+
+    //Windows that are resized by hand open at the same size next time, if the settings are saved.
+    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
+        if !self.config.display.fullscreen {
+            self.config.display.width = width as u32;
+            self.config.display.height = height as u32;
+        }
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.save_game();
+        false
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
+        for (player, action) in self.key_actions(keycode) {
+            self.action_up(player, action);
+        }
+    }
+
+    //In a game buttons do what they are bound to, elsewhere the D-pad and face buttons work the
+    //menus like the arrow keys, Enter and Escape.
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        match self.screen {
+            Screen::Playing => {
+                if let Some(action) = self.button_action(btn) {
+                    let player = self.gamepad_player(id);
+                    self.action_down(ctx, player, action);
+                }
             }
+            Screen::Controls if self.binding => {
+                self.binding = false;
+                if let Some(name) = button_name(btn) {
+                    let action = ACTIONS[self.selected_action];
+                    self.config.gamepad.buttons.bind(action, name);
+                }
+            }
+            _ => {
+                let keycode = match btn {
+                    Button::DPadUp => KeyCode::Up,
+                    Button::DPadDown => KeyCode::Down,
+                    Button::DPadLeft => KeyCode::Left,
+                    Button::DPadRight => KeyCode::Right,
+                    Button::South | Button::Start => KeyCode::Return,
+                    Button::East | Button::Select => KeyCode::Escape,
+                    _ => return,
+                };
+                self.key_down_event(ctx, keycode, KeyMods::NONE, false);
+            }
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, id: GamepadId) {
+        if let Some(action) = self.button_action(btn) {
+            let player = self.gamepad_player(id);
+            self.action_up(player, action);
+        }
+    }
+
+    //The left stick moves and soft drops like the D-pad once it is pushed past the deadzone.
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        let deadzone = self.config.gamepad.deadzone;
+        let (index, action) = match axis {
+            Axis::LeftStickX if value < -deadzone => (0, Some(Action::MoveLeft)),
+            Axis::LeftStickX if value > deadzone => (0, Some(Action::MoveRight)),
+            Axis::LeftStickX => (0, None),
+            Axis::LeftStickY if value < -deadzone => (1, Some(Action::SoftDrop)),
+            Axis::LeftStickY => (1, None),
+            _ => return,
+        };
+        let player = self.gamepad_player(id);
+        let stick = match (player, self.rival.as_mut()) {
+            (1, Some(rival)) => &mut rival.stick,
+            _ => &mut self.stick,
+        };
+        let released = stick[index];
+        if released == action {
+            return;
+        }
+        stick[index] = action;
+        if let Some(released) = released {
+            self.action_up(player, released);
+        }
+        if let (Some(pushed), Screen::Playing) = (action, self.screen) {
+            self.action_down(ctx, player, pushed);
         }
     }
 }
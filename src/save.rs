@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::engine::*;
+use crate::pieces::PieceSet;
+use crate::replay::*;
+use crate::types::Tetromino;
+
+//Version of the save format. Saves of other versions are refused rather than loaded into a game
+//that may not match them.
+pub const SAVE_VERSION: u32 = 2;
+
+//Only the version is read at first, so saves from other versions can be told apart from broken
+//ones.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    version: u32,
+    piece_set: &'a str,
+    //Seed and inputs of the game so far, so its replay carries on when it is resumed.
+    seed: u64,
+    inputs: String,
+    engine: &'a Engine,
+}
+
+#[derive(Deserialize)]
+struct LoadFile {
+    piece_set: String,
+    seed: u64,
+    inputs: String,
+    engine: Engine,
+}
+
+//Saves a game in progress along with what was recorded of it.
+pub fn save_game(path: &Path, engine: &Engine, replay: &Replay) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let file = SaveFile {
+        version: SAVE_VERSION,
+        piece_set: &engine.pieces.name,
+        seed: replay.seed,
+        inputs: encode_inputs(&replay.inputs),
+        engine,
+    };
+    let contents = serde_json::to_string(&file).map_err(|e| e.to_string())?;
+    fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+//Loads a saved game and the replay recorded of it so far. Saves that don't fit the game anymore,
+//like ones whose piece set changed, are refused.
+pub fn load_game(path: &Path, piece_sets: &[Arc<PieceSet>]) -> Result<(Engine, Replay), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let header: SaveHeader =
+        serde_json::from_str(&contents).map_err(|_| String::from("the save is damaged"))?;
+    if header.version != SAVE_VERSION {
+        return Err(format!(
+            "the game was saved by another version of Rustris (save version {}, expected {})",
+            header.version, SAVE_VERSION
+        ));
+    }
+    let LoadFile {
+        piece_set,
+        seed,
+        inputs,
+        mut engine,
+    } = serde_json::from_str(&contents).map_err(|e| format!("the save is damaged: {}", e))?;
+
+    engine.pieces = piece_sets
+        .iter()
+        .find(|set| set.name == piece_set)
+        .ok_or_else(|| format!("the piece set {} is no longer available", piece_set))?
+        .clone();
+    check_engine(&engine)?;
+
+    let mut replay = Replay::new(seed, engine.mode, engine.options, &piece_set);
+    replay.inputs = decode_inputs(&inputs, MAX_INPUT_FRAMES)?;
+    Ok((engine, replay))
+}
+
+//Checks that a loaded game can be played without running off its grid or piece set.
+fn check_engine(engine: &Engine) -> Result<(), String> {
+    engine.options.validate()?;
+    engine.options.validate_pieces(&engine.pieces)?;
+
+    let width = engine.options.width as usize;
+    let height = (engine.options.height + engine.options.hidden_rows) as usize;
+    if engine.grid.width as usize != width
+        || engine.grid.height as usize != height
+        || engine.grid.blocks.len() != width
+        || engine
+            .grid
+            .blocks
+            .iter()
+            .any(|column| column.len() != height)
+    {
+        return Err(String::from("the grid doesn't match the game options"));
+    }
+
+    let pieces = engine.pieces.len();
+    if [
+        engine.tetromino,
+        engine.tetromino_next,
+        engine.tetromino_hold,
+    ]
+    .iter()
+    .flatten()
+    .any(|tetromino| tetromino.piece >= pieces)
+    {
+        return Err(String::from("the piece set doesn't have the saved pieces"));
+    }
+
+    let scale = engine.options.scale();
+    let on_grid = |tetromino: &Tetromino| {
+        tetromino.blocks(&engine.pieces).iter().all(|block| {
+            let x = tetromino.position.x + block.position.x;
+            let y = tetromino.position.y + block.position.y;
+            x >= 0.0 && x < width as f32 && y >= 0.0 && y < height as f32
+        })
+    };
+    //The next and held tetrominoes are brought out where they were saved, so they have to be on
+    //the grid as well.
+    for tetromino in [
+        engine.tetromino,
+        engine.tetromino_next,
+        engine.tetromino_hold,
+    ]
+    .iter()
+    .flatten()
+    {
+        if tetromino.scale != scale {
+            return Err(String::from(
+                "the saved tetrominoes don't match the game options",
+            ));
+        }
+        if !on_grid(tetromino) {
+            return Err(String::from("a saved tetromino is off the grid"));
+        }
+    }
+    //A game that ended can have its last tetromino overlap the stack, as that is how it ended.
+    if let Some(tetromino) = &engine.tetromino {
+        if engine.phase != Phase::GameOver && engine.collides(tetromino) {
+            return Err(String::from("the tetromino in play overlaps the stack"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BlockState;
+
+    fn playing() -> Engine {
+        let pieces = Arc::new(PieceSet::standard());
+        let mut engine = Engine::new(GameMode::Marathon, GameOptions::default(), pieces, 1);
+        engine.start();
+        while engine.tetromino.is_none() {
+            engine.tick(FrameInput::default());
+        }
+        engine
+    }
+
+    #[test]
+    fn games_being_played_are_accepted() {
+        assert_eq!(check_engine(&playing()), Ok(()));
+    }
+
+    #[test]
+    fn tetrominoes_off_the_grid_are_refused() {
+        let mut engine = playing();
+        engine.tetromino.as_mut().unwrap().position.x = -3.0;
+        assert!(check_engine(&engine).is_err());
+
+        let mut engine = playing();
+        engine.tetromino_next.as_mut().unwrap().position.y = 100.0;
+        assert!(check_engine(&engine).is_err());
+    }
+
+    #[test]
+    fn tetrominoes_in_the_stack_are_refused() {
+        let mut engine = playing();
+        let tetromino = engine.tetromino.unwrap();
+        for block in tetromino.blocks(&engine.pieces) {
+            let x = (tetromino.position.x + block.position.x) as usize;
+            let y = (tetromino.position.y + block.position.y) as usize;
+            engine.grid.blocks[x][y].state = BlockState::Filled;
+        }
+        assert!(check_engine(&engine).is_err());
+    }
+
+    #[test]
+    fn tetrominoes_of_another_size_are_refused() {
+        let mut engine = playing();
+        engine.tetromino.as_mut().unwrap().scale = 2;
+        assert!(check_engine(&engine).is_err());
+    }
+}
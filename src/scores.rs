@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::*;
+use crate::master::*;
+
+//Entries kept in each leaderboard.
+pub const MAX_SCORES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+//A finished game on a leaderboard.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: u64,
+    pub lines: u32,
+    pub level: u32,
+    //Length of the game in frames.
+    pub frames: u32,
+    //Seconds since the Unix epoch when the game ended.
+    pub date: u64,
+    pub seed: String,
+    //Grade reached, for master mode games.
+    #[serde(default)]
+    pub grade: Option<Grade>,
+}
+
+impl ScoreEntry {
+    //Makes an entry for a game that just ended. The name is filled in by the player.
+    pub fn from_game(engine: &Engine, seed: u64) -> ScoreEntry {
+        let (score, level, grade) = match engine.mode {
+            GameMode::Marathon | GameMode::Drill => (engine.score, engine.get_level(), None),
+            GameMode::Master => (
+                engine.master.score,
+                engine.master.level,
+                Some(engine.master.grade()),
+            ),
+        };
+        ScoreEntry {
+            name: String::new(),
+            score,
+            lines: engine.lines(),
+            level,
+            frames: engine.frame,
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            seed: format!("{:016x}", seed),
+            grade,
+        }
+    }
+
+    //Orders entries from best to worst. Marathon games are ranked by score, master mode games by
+    //grade and then by how far and how fast they got.
+    fn rank(&self, other: &ScoreEntry, mode: GameMode) -> Ordering {
+        match mode {
+            GameMode::Marathon | GameMode::Drill => (
+                other.score,
+                other.lines,
+                Reverse(other.frames),
+            )
+                .cmp(&(self.score, self.lines, Reverse(self.frames))),
+            GameMode::Master => (other.grade, other.level, Reverse(other.frames)).cmp(&(
+                self.grade,
+                self.level,
+                Reverse(self.frames),
+            )),
+        }
+    }
+}
+
+//The leaderboards of every mode.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HighScores {
+    #[serde(default)]
+    marathon: Vec<ScoreEntry>,
+    #[serde(default)]
+    master: Vec<ScoreEntry>,
+}
+
+impl HighScores {
+    //Reads the leaderboards. There are none yet if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<HighScores, String> {
+        if !path.exists() {
+            return Ok(HighScores::default());
+        }
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    //Drills are practice, and have no leaderboard.
+    pub fn table(&self, mode: GameMode) -> &[ScoreEntry] {
+        match mode {
+            GameMode::Marathon => &self.marathon,
+            GameMode::Master => &self.master,
+            GameMode::Drill => &[],
+        }
+    }
+
+    fn table_mut(&mut self, mode: GameMode) -> Option<&mut Vec<ScoreEntry>> {
+        match mode {
+            GameMode::Marathon => Some(&mut self.marathon),
+            GameMode::Master => Some(&mut self.master),
+            GameMode::Drill => None,
+        }
+    }
+
+    //Whether the entry would make it onto the leaderboard of the mode.
+    pub fn qualifies(&self, mode: GameMode, entry: &ScoreEntry) -> bool {
+        if mode == GameMode::Drill {
+            return false;
+        }
+        let table = self.table(mode);
+        table.len() < MAX_SCORES
+            || table
+                .iter()
+                .any(|other| entry.rank(other, mode) == Ordering::Less)
+    }
+
+    //Puts the entry on the leaderboard and returns its place, if it made it.
+    pub fn insert(&mut self, mode: GameMode, entry: ScoreEntry) -> Option<usize> {
+        let table = self.table_mut(mode)?;
+        let index = table
+            .iter()
+            .position(|other| entry.rank(other, mode) == Ordering::Less)
+            .unwrap_or(table.len());
+        if index >= MAX_SCORES {
+            return None;
+        }
+        table.insert(index, entry);
+        table.truncate(MAX_SCORES);
+        Some(index)
+    }
+}
+
+//Formats seconds since the Unix epoch as a yyyy-mm-dd date, in UTC.
+pub fn format_date(seconds: u64) -> String {
+    //Converts days to a civil date, as described in Howard Hinnant's date algorithms.
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u64, frames: u32, grade: Option<Grade>, level: u32) -> ScoreEntry {
+        ScoreEntry {
+            name: format!("{}", score),
+            score,
+            lines: 0,
+            level,
+            frames,
+            date: 0,
+            seed: String::new(),
+            grade,
+        }
+    }
+
+    #[test]
+    fn marathon_ranks_by_score_then_time() {
+        let mut scores = HighScores::default();
+        assert_eq!(
+            scores.insert(GameMode::Marathon, entry(100, 600, None, 1)),
+            Some(0)
+        );
+        assert_eq!(
+            scores.insert(GameMode::Marathon, entry(300, 600, None, 1)),
+            Some(0)
+        );
+        assert_eq!(
+            scores.insert(GameMode::Marathon, entry(100, 300, None, 1)),
+            Some(1)
+        );
+        let table = scores.table(GameMode::Marathon);
+        assert_eq!(
+            table.iter().map(|entry| entry.frames).collect::<Vec<_>>(),
+            vec![600, 300, 600]
+        );
+    }
+
+    #[test]
+    fn master_ranks_by_grade_before_score() {
+        let mut scores = HighScores::default();
+        scores.insert(GameMode::Master, entry(50_000, 600, Some(Grade::S3), 400));
+        let place = scores.insert(GameMode::Master, entry(0, 600, Some(Grade::GM), 999));
+        assert_eq!(place, Some(0));
+        let place = scores.insert(GameMode::Master, entry(90_000, 600, Some(Grade::S3), 500));
+        assert_eq!(place, Some(1));
+    }
+
+    #[test]
+    fn full_tables_only_take_better_entries() {
+        let mut scores = HighScores::default();
+        for score in 1..=MAX_SCORES as u64 {
+            scores.insert(GameMode::Marathon, entry(score * 10, 600, None, 1));
+        }
+        assert!(!scores.qualifies(GameMode::Marathon, &entry(5, 600, None, 1)));
+        assert_eq!(
+            scores.insert(GameMode::Marathon, entry(5, 600, None, 1)),
+            None
+        );
+        assert!(scores.qualifies(GameMode::Marathon, &entry(15, 600, None, 1)));
+        assert_eq!(
+            scores.insert(GameMode::Marathon, entry(15, 600, None, 1)),
+            Some(9)
+        );
+        assert_eq!(scores.table(GameMode::Marathon).len(), MAX_SCORES);
+    }
+
+    #[test]
+    fn drills_have_no_leaderboard() {
+        let mut scores = HighScores::default();
+        assert!(!scores.qualifies(GameMode::Drill, &entry(100, 600, None, 1)));
+        assert_eq!(
+            scores.insert(GameMode::Drill, entry(100, 600, None, 1)),
+            None
+        );
+    }
+}
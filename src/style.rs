@@ -0,0 +1,223 @@
+use ggez::graphics;
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::*;
+use ggez::nalgebra::geometry::Point2;
+use ggez::nalgebra::Vector2;
+use ggez::{Context, GameResult};
+
+use crate::pieces::PieceSet;
+use crate::theme::Theme;
+use crate::types::{self, Block};
+
+//Width of the lines around blocks and the grid.
+const LINE_WIDTH: f32 = 2.0;
+//Dashes along each side of a block in dashed outlines.
+const DASHES: u32 = 3;
+
+impl From<types::Color> for Color {
+    fn from(color: types::Color) -> Color {
+        Color::new(color.r, color.g, color.b, color.a)
+    }
+}
+
+//A theme with its font and skin loaded. If either fails to load the built in font or flat blocks
+//are used instead.
+pub struct Style {
+    pub theme: Theme,
+    font: Option<Font>,
+    //The skin's atlas, and the size of its tiles in pixels.
+    atlas: Option<(Image, f32)>,
+}
+
+impl Style {
+    pub fn new(ctx: &mut Context, theme: &Theme) -> Style {
+        let font = theme.font.as_ref().and_then(|font| {
+            Font::new(ctx, format!("/fonts/{}", font))
+                .map_err(|e| println!("Couldn't load font {}: {}", font, e))
+                .ok()
+        });
+        let atlas = theme.skin.as_ref().and_then(|skin| {
+            let image = Image::new(ctx, format!("/skins/{}", skin.image))
+                .map_err(|e| println!("Couldn't load skin {}: {}", skin.image, e))
+                .ok()?;
+            let size = image.height() as f32;
+            if size == 0.0 || (image.width() as f32) < size * (skin.tiles.len() + 1) as f32 {
+                println!(
+                    "Skin {} needs {} square tiles in a row",
+                    skin.image,
+                    skin.tiles.len() + 1
+                );
+                return None;
+            }
+            Some((image, size))
+        });
+
+        Style {
+            theme: theme.clone(),
+            font,
+            atlas,
+        }
+    }
+
+    pub fn font(&self) -> Font {
+        self.font.unwrap_or_default()
+    }
+
+    //Text in the theme's font and color.
+    pub fn text<T: Into<TextFragment>>(&self, text: T) -> Text {
+        Text::new(
+            TextFragment::new(text)
+                .font(self.font())
+                .color(Color::from(self.theme.text)),
+        )
+    }
+
+    //Starts collecting blocks of `size` pixels to draw all at once.
+    pub fn blocks<'a>(&'a self, pieces: &'a PieceSet, size: f32) -> BlockBatch<'a> {
+        BlockBatch {
+            style: self,
+            pieces,
+            size,
+            mesh: MeshBuilder::new(),
+            sprites: self
+                .atlas
+                .as_ref()
+                .map(|(image, _)| SpriteBatch::new(image.clone())),
+            empty: true,
+        }
+    }
+}
+
+//Blocks drawn in one go, either as flat squares or from the skin.
+pub struct BlockBatch<'a> {
+    style: &'a Style,
+    pieces: &'a PieceSet,
+    size: f32,
+    mesh: MeshBuilder,
+    sprites: Option<SpriteBatch>,
+    empty: bool,
+}
+
+impl<'a> BlockBatch<'a> {
+    //Adds a block whose top left corner is at (x, y), counted in blocks.
+    pub fn add(&mut self, x: f32, y: f32, block: &Block, alpha: f32) {
+        let theme = &self.style.theme;
+        let pieces = self.pieces;
+        let color = Color::from(theme.block_color(pieces, block));
+        let rect = Rect::new(x * self.size, y * self.size, self.size, self.size);
+
+        match (self.sprites.as_mut(), self.style.atlas.as_ref()) {
+            (Some(sprites), Some((image, tile_size))) => {
+                let tiles = &theme.skin.as_ref().unwrap().tiles;
+                let tile = block
+                    .piece
+                    .and_then(|piece| pieces.pieces.get(piece))
+                    .and_then(|piece| tiles.iter().position(|name| *name == piece.name))
+                    .map(|index| index + 1);
+                let tint = match tile {
+                    Some(_) => graphics::WHITE,
+                    None => color,
+                };
+                let tile_width = tile_size / image.width() as f32;
+                sprites.add(
+                    DrawParam::new()
+                        .src(Rect::new(
+                            tile.unwrap_or(0) as f32 * tile_width,
+                            0.0,
+                            tile_width,
+                            1.0,
+                        ))
+                        .dest(Point2::new(rect.x, rect.y))
+                        .scale(Vector2::new(self.size / tile_size, self.size / tile_size))
+                        .color(Color::new(tint.r, tint.g, tint.b, alpha)),
+                );
+            }
+            _ => {
+                self.mesh.rectangle(
+                    DrawMode::fill(),
+                    rect,
+                    Color::new(color.r, color.g, color.b, alpha),
+                );
+                self.mesh.rectangle(
+                    DrawMode::stroke(LINE_WIDTH),
+                    rect,
+                    Color::new(
+                        theme.grid_lines.r,
+                        theme.grid_lines.g,
+                        theme.grid_lines.b,
+                        alpha,
+                    ),
+                );
+                self.empty = false;
+            }
+        }
+    }
+
+    //Adds just the outline of a block, in its color.
+    pub fn add_outline(&mut self, x: f32, y: f32, block: &Block) {
+        let color = Color::from(self.style.theme.block_color(self.pieces, block));
+        self.mesh.rectangle(
+            DrawMode::stroke(LINE_WIDTH),
+            Rect::new(x * self.size, y * self.size, self.size, self.size),
+            color,
+        );
+        self.empty = false;
+    }
+
+    //Adds a dashed line around the outside of a group of cells, counted in blocks, in the color of
+    //a block.
+    pub fn add_dashed_outline(&mut self, cells: &[(i32, i32)], block: &Block) -> GameResult<()> {
+        let color = Color::from(self.style.theme.block_color(self.pieces, block));
+        let size = self.size;
+        //The neighbour across each side of a cell, and the corners the side runs between.
+        let sides = [
+            ((0, -1), (0.0, 0.0), (1.0, 0.0)),
+            ((1, 0), (1.0, 0.0), (1.0, 1.0)),
+            ((0, 1), (1.0, 1.0), (0.0, 1.0)),
+            ((-1, 0), (0.0, 1.0), (0.0, 0.0)),
+        ];
+        for (x, y) in cells {
+            for ((dx, dy), (x1, y1), (x2, y2)) in sides.iter() {
+                if cells.contains(&(x + dx, y + dy)) {
+                    continue;
+                }
+                let point = |along: f32| {
+                    Point2::new(
+                        (*x as f32 + x1 + (x2 - x1) * along) * size,
+                        (*y as f32 + y1 + (y2 - y1) * along) * size,
+                    )
+                };
+                for dash in 0..DASHES {
+                    let start = dash as f32 / DASHES as f32;
+                    let end = start + 0.5 / DASHES as f32;
+                    self.mesh
+                        .line(&[point(start), point(end)], LINE_WIDTH, color)?;
+                }
+                self.empty = false;
+            }
+        }
+        Ok(())
+    }
+
+    //Adds a frame in the color of the grid lines, in pixels.
+    pub fn add_frame(&mut self, rect: Rect) {
+        self.mesh.rectangle(
+            DrawMode::stroke(LINE_WIDTH),
+            rect,
+            Color::from(self.style.theme.grid_lines),
+        );
+        self.empty = false;
+    }
+
+    //Draws the blocks with (0, 0) at `origin`.
+    pub fn draw(self, ctx: &mut Context, origin: Point2<f32>) -> GameResult<()> {
+        if let Some(sprites) = self.sprites.as_ref() {
+            graphics::draw(ctx, sprites, DrawParam::default().dest(origin))?;
+        }
+        if !self.empty {
+            let mesh = self.mesh.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default().dest(origin))?;
+        }
+        Ok(())
+    }
+}
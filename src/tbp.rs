@@ -0,0 +1,529 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ai::{Board, Pilot, Placement};
+use crate::bot::Bot;
+use crate::engine::*;
+use crate::pieces::PieceSet;
+use crate::types::*;
+
+//Size of the board bots are sent. Rows above the grid are sent empty.
+const BOARD_COLUMNS: u32 = 10;
+const BOARD_ROWS: u32 = 40;
+
+//The pieces bots know, with their cells facing north around the center they are placed by. Y
+//counts up, as it does on the board.
+const PIECES: [(&str, [(i32, i32); 4]); 7] = [
+    ("I", [(-1, 0), (0, 0), (1, 0), (2, 0)]),
+    ("O", [(0, 0), (1, 0), (0, 1), (1, 1)]),
+    ("T", [(-1, 0), (0, 0), (1, 0), (0, 1)]),
+    ("L", [(-1, 0), (0, 0), (1, 0), (1, 1)]),
+    ("J", [(-1, 0), (0, 0), (1, 0), (-1, 1)]),
+    ("S", [(-1, 0), (0, 0), (0, 1), (1, 1)]),
+    ("Z", [(-1, 1), (0, 1), (0, 0), (1, 0)]),
+];
+
+//Orientations in clockwise order, starting from the one pieces spawn in.
+const ORIENTATIONS: [&str; 4] = ["north", "east", "south", "west"];
+const ROTATIONS: [Rotation; 4] = [Rotation::_0, Rotation::_90, Rotation::_180, Rotation::_270];
+
+//How long a bot gets to introduce itself and to accept the rules.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+//How long a bot the game waits for gets to suggest a move.
+const SUGGESTION_TIMEOUT: Duration = Duration::from_secs(10);
+//How long a bot gets to quit before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+//Messages sent to the bot.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules,
+    Start {
+        hold: Option<String>,
+        //The piece in play first.
+        queue: Vec<String>,
+        combo: u32,
+        back_to_back: bool,
+        //Rows from the bottom up, with the name of the piece that filled each cell, or G for
+        //garbage.
+        board: Vec<Vec<Option<String>>>,
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        placement: Move,
+    },
+    NewPiece {
+        piece: String,
+    },
+    Quit,
+}
+
+//Messages the bot sends back.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    //Moves the bot would make, the best first.
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Move {
+    location: Location,
+    spin: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Location {
+    #[serde(rename = "type")]
+    piece: String,
+    orientation: String,
+    x: i32,
+    y: i32,
+}
+
+//Checks that bots can play a game with these options and pieces: the seven tetrominoes on a grid
+//10 columns wide.
+pub fn supports(options: &GameOptions, pieces: &PieceSet) -> Result<(), String> {
+    if options.width != BOARD_COLUMNS {
+        return Err(format!(
+            "bots play on grids {} columns wide",
+            BOARD_COLUMNS
+        ));
+    }
+    if options.big {
+        return Err(String::from("bots can't play big mode"));
+    }
+    if options.height + options.hidden_rows > BOARD_ROWS {
+        return Err(format!("bots play on grids up to {} rows high", BOARD_ROWS));
+    }
+    piece_names(pieces).map(|_| ())
+}
+
+//Names bots know each piece of the set by. Pieces are matched by their shape, since some of
+//the standard ones have the names of their mirror images in Rustris.
+fn piece_names(pieces: &PieceSet) -> Result<Vec<&'static str>, String> {
+    pieces
+        .pieces
+        .iter()
+        .map(|piece| {
+            //Rustris counts y down, so the piece is flipped to compare it. Pieces may spawn
+            //facing another way than they do for bots.
+            let cells = normalized(piece.rotations[0].iter().map(|(x, y)| (*x, -*y)).collect());
+            PIECES
+                .iter()
+                .find(|(_, north)| {
+                    (0..ORIENTATIONS.len()).any(|turns| normalized(turned(north, turns)) == cells)
+                })
+                .map(|(name, _)| *name)
+                .ok_or_else(|| format!("bots don't know the {} piece", piece.name))
+        })
+        .collect()
+}
+
+//Cells of a piece facing north, turned clockwise `turns` times. Turning takes (x, y) to (y, -x).
+fn turned(north: &[(i32, i32)], turns: usize) -> Vec<(i32, i32)> {
+    north
+        .iter()
+        .map(|cell| (0..turns).fold(*cell, |(x, y), _| (y, -x)))
+        .collect()
+}
+
+//Cells moved so the lowest x and y are 0, in order, to compare shapes.
+fn normalized(mut cells: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let min_x = cells.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let min_y = cells.iter().map(|(_, y)| *y).min().unwrap_or(0);
+    cells.iter_mut().for_each(|(x, y)| {
+        *x -= min_x;
+        *y -= min_y;
+    });
+    cells.sort();
+    cells
+}
+
+//Lets an external bot that speaks the Tetris Bot Protocol (TBP) play, by sending it the game as
+//it goes and steering each tetromino to the move it suggests. The bot is kept up to date with
+//the moves it made and the pieces that come up, and is started over on the actual game whenever
+//it gets out of step with it.
+pub struct TbpBot {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<BotMessage>,
+    //Whether the game waits for the bot to suggest a move, instead of playing on while it thinks.
+    wait: bool,
+    pilot: Pilot,
+    //Names the bot knows the pieces of the game by.
+    names: Vec<&'static str>,
+    //Tetrominoes locked when the one in play came in, to tell when the next one does.
+    piece: Option<u32>,
+    started: bool,
+    //What the bot thinks the game looks like: the queue with the piece in play first, the held
+    //piece and the stack. The stack is None when the bot's last move couldn't be followed.
+    queue: Vec<usize>,
+    hold: Option<usize>,
+    board: Option<Board>,
+    //Set between asking the bot for a move and getting one.
+    suggesting: bool,
+    //Set when the tetromino the bot is thinking about locked before it suggested a move.
+    stale: bool,
+    //Set once the bot stopped working. Tetrominoes are dropped where they spawn after that.
+    failed: bool,
+}
+
+impl TbpBot {
+    //Starts the bot with `command`, the program followed by its arguments, and agrees on the
+    //rules with it.
+    pub fn launch(command: &[String], wait: bool) -> Result<TbpBot, String> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| String::from("no command to start the bot with"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", program, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        //The bot's messages are read on their own thread, so the game never blocks on them.
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                match serde_json::from_str::<BotMessage>(&line) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => println!("Ignoring message from the bot: {}: {}", e, line),
+                }
+            }
+        });
+
+        let mut bot = TbpBot {
+            name: program.clone(),
+            child,
+            stdin,
+            messages,
+            wait,
+            pilot: Pilot::new(),
+            names: Vec::new(),
+            piece: None,
+            started: false,
+            queue: Vec::new(),
+            hold: None,
+            board: None,
+            suggesting: false,
+            stale: false,
+            failed: false,
+        };
+
+        match bot.receive(HANDSHAKE_TIMEOUT)? {
+            BotMessage::Info { name, version } => bot.name = format!("{} {}", name, version),
+            _ => return Err(String::from("the bot didn't introduce itself")),
+        }
+        bot.send(&FrontendMessage::Rules)?;
+        match bot.receive(HANDSHAKE_TIMEOUT)? {
+            BotMessage::Ready => Ok(bot),
+            BotMessage::Error { reason } => Err(format!("the bot refused the rules: {}", reason)),
+            _ => Err(String::from("the bot didn't accept the rules")),
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> Result<(), String> {
+        let line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("couldn't write to the bot: {}", e))
+    }
+
+    //Waits for the next message the bot sends that the game understands.
+    fn receive(&mut self, timeout: Duration) -> Result<BotMessage, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.messages.recv_timeout(left) {
+                Ok(BotMessage::Unknown) => continue,
+                Ok(message) => return Ok(message),
+                Err(_) => return Err(String::from("the bot didn't respond")),
+            }
+        }
+    }
+
+    //The next message from the bot, if it sent one yet. Waits for it when the game waits for the
+    //bot.
+    fn poll(&mut self) -> Result<Option<BotMessage>, String> {
+        if self.wait {
+            return self.receive(SUGGESTION_TIMEOUT).map(Some);
+        }
+        loop {
+            match self.messages.try_recv() {
+                Ok(BotMessage::Unknown) => continue,
+                Ok(message) => return Ok(Some(message)),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(String::from("the bot stopped running"))
+                }
+            }
+        }
+    }
+
+    //Brings the bot up to date with the game as a new tetromino comes into play. If the game
+    //went the way the bot expects it only needs to hear about the new pieces in the queue,
+    //otherwise it starts over on the game as it is.
+    fn sync(&mut self, engine: &Engine) -> Result<(), String> {
+        let queue = [engine.tetromino, engine.tetromino_next]
+            .iter()
+            .filter_map(|tetromino| tetromino.map(|tetromino| tetromino.piece))
+            .collect::<Vec<_>>();
+        let hold = engine.tetromino_hold.map(|tetromino| tetromino.piece);
+        let board = Board::from_grid(&engine.grid);
+        if self.names.is_empty() {
+            self.names = piece_names(&engine.pieces)?;
+        }
+        let names = self.names.clone();
+        let name = |piece: usize| String::from(names[piece]);
+
+        let in_step = self.started
+            && self.board.as_ref() == Some(&board)
+            && self.hold == hold
+            && queue.starts_with(&self.queue);
+        if in_step {
+            for piece in queue[self.queue.len()..].iter() {
+                self.send(&FrontendMessage::NewPiece { piece: name(*piece) })?;
+            }
+        } else {
+            if self.started {
+                self.send(&FrontendMessage::Stop)?;
+            }
+            self.send(&FrontendMessage::Start {
+                hold: hold.map(name),
+                queue: queue.iter().map(|piece| name(*piece)).collect(),
+                combo: 0,
+                back_to_back: false,
+                board: board_rows(engine, &names),
+            })?;
+            self.started = true;
+        }
+        self.queue = queue;
+        self.hold = hold;
+        self.board = Some(board);
+        Ok(())
+    }
+
+    //Follows the move the bot suggested: tells the bot it was made, keeps track of how the game
+    //should look after it and steers the tetromino there.
+    fn play(&mut self, engine: &Engine, moves: Vec<Move>) -> Result<(), String> {
+        let chosen = match moves.into_iter().next() {
+            Some(chosen) => chosen,
+            None => {
+                //The bot has no move to make, so it is started over on the next tetromino.
+                self.board = None;
+                self.pilot.set_target(None);
+                return Ok(());
+            }
+        };
+        self.send(&FrontendMessage::Play {
+            placement: chosen.clone(),
+        })?;
+
+        let placement = placement(engine, &self.names, &chosen.location);
+        let placed = placement.and_then(|placement| {
+            let piece = placement.tetromino.piece;
+            if self.queue.first() == Some(&piece) {
+                self.queue.remove(0);
+            } else {
+                //The bot holds the piece in play and places the held one, or the next one if
+                //nothing was held.
+                let held = self.hold.replace(*self.queue.first()?);
+                self.queue.remove(0);
+                if held.is_none() && self.queue.first() == Some(&piece) {
+                    self.queue.remove(0);
+                } else if held != Some(piece) {
+                    return None;
+                }
+            }
+            Some(placement)
+        });
+        //A move for another piece than the ones in play and in hold is not followed.
+        match (placed, self.board.as_mut()) {
+            (Some(placement), Some(board)) => {
+                board.place(&engine.pieces, &placement.tetromino);
+                self.pilot.set_target(Some(placement));
+            }
+            (placed, _) => {
+                self.board = None;
+                self.pilot.set_target(placed);
+            }
+        }
+        Ok(())
+    }
+
+    //Asks the bot for a move for the tetromino in play.
+    fn ask(&mut self, engine: &Engine) -> Result<(), String> {
+        self.sync(engine)?;
+        self.send(&FrontendMessage::Suggest)?;
+        self.suggesting = true;
+        Ok(())
+    }
+
+    fn fail(&mut self, e: String) {
+        println!("The bot stopped playing: {}", e);
+        self.failed = true;
+    }
+}
+
+impl Bot for TbpBot {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn next_input(&mut self, engine: &Engine) -> FrameInput {
+        let mut input = FrameInput::default();
+        if engine.phase != Phase::Falling || engine.tetromino.is_none() {
+            return input;
+        }
+        if self.failed {
+            input.press(INPUT_HARD_DROP);
+            return input;
+        }
+
+        if self.piece != Some(engine.pieces_locked) {
+            self.piece = Some(engine.pieces_locked);
+            self.pilot.set_target(None);
+            //A move still on its way is for the tetromino that locked. The bot is only asked
+            //about this one once that move has come in and been thrown away.
+            self.stale = self.suggesting;
+            if !self.stale {
+                if let Err(e) = self.ask(engine) {
+                    self.fail(e);
+                    return input;
+                }
+            }
+        }
+
+        if self.suggesting {
+            let played = match self.poll() {
+                Ok(Some(BotMessage::Suggestion { .. })) if self.stale => {
+                    self.suggesting = false;
+                    self.stale = false;
+                    //The bot still expects its move to be made, so it is started over.
+                    self.board = None;
+                    self.ask(engine)
+                }
+                Ok(Some(BotMessage::Suggestion { moves })) => {
+                    self.suggesting = false;
+                    self.play(engine, moves)
+                }
+                Ok(Some(BotMessage::Error { reason })) => Err(reason),
+                Ok(_) => return input,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = played {
+                self.fail(e);
+            }
+            return input;
+        }
+
+        self.pilot.next_input(engine)
+    }
+}
+
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        if self.send(&FrontendMessage::Quit).is_ok() {
+            let deadline = Instant::now() + QUIT_TIMEOUT;
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = self.child.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+//The grid as bots see it: rows from the bottom up, with the name of the piece that filled each
+//cell.
+fn board_rows(engine: &Engine, names: &[&str]) -> Vec<Vec<Option<String>>> {
+    let height = engine.grid.height;
+    (0..BOARD_ROWS)
+        .map(|row| {
+            (0..engine.grid.width)
+                .map(|x| {
+                    if row >= height || !engine.grid.check_occupied(x, height - 1 - row) {
+                        return None;
+                    }
+                    let block = &engine.grid.blocks[x as usize][(height - 1 - row) as usize];
+                    let name = block.piece.and_then(|piece| names.get(piece));
+                    Some(String::from(*name.unwrap_or(&"G")))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+//Where a location a bot picked puts a tetromino on the grid.
+fn placement(engine: &Engine, names: &[&str], location: &Location) -> Option<Placement> {
+    let (_, north) = PIECES.iter().find(|(name, _)| *name == location.piece)?;
+    let turns = ORIENTATIONS
+        .iter()
+        .position(|orientation| *orientation == location.orientation)?;
+    let height = engine.grid.height as i32;
+    let mut cells = turned(north, turns)
+        .iter()
+        .map(|(x, y)| (location.x + x, height - 1 - (location.y + y)))
+        .collect::<Vec<_>>();
+    cells.sort();
+
+    let piece = names.iter().position(|name| *name == location.piece)?;
+    let hold = engine.tetromino.map(|tetromino| tetromino.piece) != Some(piece);
+    ROTATIONS.iter().find_map(|rotation| {
+        let mut shape = engine.pieces.pieces[piece].rotations[rotation.index()].clone();
+        shape.sort();
+        let (x, y) = (cells[0].0 - shape[0].0, cells[0].1 - shape[0].1);
+        let fits = shape
+            .iter()
+            .map(|(cell_x, cell_y)| (cell_x + x, cell_y + y))
+            .eq(cells.iter().copied());
+        if !fits {
+            return None;
+        }
+        Some(Placement {
+            hold,
+            tetromino: Tetromino {
+                position: Point2::new(x as f32, y as f32),
+                rotation: *rotation,
+                piece,
+                scale: 1,
+            },
+            score: 0.0,
+        })
+    })
+}
@@ -0,0 +1,164 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::pieces::PieceSet;
+use crate::types::*;
+
+const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+
+//The colors, font and block skin the game is drawn with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    //Lines around the grid and the blocks in it.
+    pub grid_lines: Color,
+    pub text: Color,
+    //Colors of pieces by name. Pieces that aren't listed keep the color of their piece set.
+    pub palette: HashMap<String, Color>,
+    //File in resources/fonts all text is written in, instead of the built in font.
+    pub font: Option<String>,
+    pub skin: Option<Skin>,
+}
+
+//Blocks drawn from a texture atlas instead of as flat squares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skin {
+    //File in resources/skins holding a row of square tiles. The first tile is tinted with the
+    //color of the piece it is drawn for.
+    pub image: String,
+    //Pieces that have a tile of their own, from the second tile on. They are drawn untinted.
+    pub tiles: Vec<String>,
+}
+
+impl Theme {
+    //The look the game always had.
+    pub fn classic() -> Theme {
+        Theme {
+            name: String::from("Classic"),
+            background: BLACK,
+            grid_lines: Color::new(0.5, 0.5, 0.5, 1.0),
+            text: WHITE,
+            palette: HashMap::new(),
+            font: None,
+            skin: None,
+        }
+    }
+
+    //White lines on black, with the tetrominoes in the Okabe-Ito colors, which stay apart for
+    //the common kinds of color blindness.
+    pub fn high_contrast() -> Theme {
+        let palette = [
+            ("I", (86, 180, 233)),
+            ("O", (240, 228, 66)),
+            ("T", (204, 121, 167)),
+            ("S", (0, 158, 115)),
+            ("Z", (213, 94, 0)),
+            ("J", (0, 114, 178)),
+            ("L", (230, 159, 0)),
+        ]
+        .iter()
+        .map(|(name, (r, g, b))| (String::from(*name), Color::from_rgb(*r, *g, *b)))
+        .collect();
+        Theme {
+            name: String::from("High contrast"),
+            background: BLACK,
+            grid_lines: WHITE,
+            text: WHITE,
+            palette,
+            font: None,
+            skin: None,
+        }
+    }
+
+    //Reads a theme from a TOML file.
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: ThemeFile =
+            toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(file.into_theme())
+    }
+
+    //Color a block is drawn in.
+    pub fn block_color(&self, pieces: &PieceSet, block: &Block) -> Color {
+        block
+            .piece
+            .and_then(|piece| pieces.pieces.get(piece))
+            .and_then(|piece| self.palette.get(&piece.name))
+            .copied()
+            .unwrap_or(block.color)
+    }
+}
+
+//Loads every theme in `dir`, after the built in ones. Themes that fail to load are reported back
+//instead of stopping the others from loading.
+pub fn load_themes(dir: &Path) -> (Vec<Theme>, Vec<String>) {
+    let mut themes = vec![Theme::classic(), Theme::high_contrast()];
+    let mut errors = Vec::new();
+
+    let mut paths = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+
+    for path in paths {
+        match Theme::load(&path) {
+            Ok(theme) => themes.push(theme),
+            Err(e) => errors.push(e),
+        }
+    }
+    (themes, errors)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: String,
+    background: [u8; 3],
+    grid_lines: [u8; 3],
+    text: [u8; 3],
+    #[serde(default)]
+    palette: HashMap<String, [u8; 3]>,
+    #[serde(default)]
+    font: Option<String>,
+    #[serde(default)]
+    skin: Option<SkinFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkinFile {
+    image: String,
+    #[serde(default)]
+    tiles: Vec<String>,
+}
+
+impl ThemeFile {
+    fn into_theme(self) -> Theme {
+        let color = |[r, g, b]: [u8; 3]| Color::from_rgb(r, g, b);
+        Theme {
+            name: self.name,
+            background: color(self.background),
+            grid_lines: color(self.grid_lines),
+            text: color(self.text),
+            palette: self
+                .palette
+                .into_iter()
+                .map(|(name, rgb)| (name, color(rgb)))
+                .collect(),
+            font: self.font,
+            skin: self.skin.map(|skin| Skin {
+                image: skin.image,
+                tiles: skin.tiles,
+            }),
+        }
+    }
+}
@@ -1,37 +1,57 @@
-use crate::types::TetrominoType::{I, J, L, O, S, T, Z};
-use ggez::graphics::Color;
-use ggez::nalgebra::geometry::Point2;
-use rand::distributions::{Distribution, Standard};
-
-//I
-const COLOR_I: Color = Color::new(42.0 / 255.0, 80.0 / 255.0, 230.0 / 255.0, 255.0 / 255.0);
-//O
-const COLOR_O: Color = Color::new(255.0 / 255.0, 242.0 / 255.0, 117.0 / 255.0, 255.0 / 255.0);
-//T
-const COLOR_T: Color = Color::new(230.0 / 255.0, 46.0 / 255.0, 187.0 / 255.0, 255.0 / 255.0);
-//S
-const COLOR_S: Color = Color::new(51.0 / 255.0, 243.0 / 255.0, 115.0 / 255.0, 255.0 / 255.0);
-//Z
-const COLOR_Z: Color = Color::new(255.0 / 255.0, 136.0 / 255.0, 16.0 / 255.0, 255.0 / 255.0);
-//J
-const COLOR_J: Color = Color::new(31.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0);
-//L
-const COLOR_L: Color = Color::new(208.0 / 255.0, 54.0 / 255.0, 54.0 / 255.0, 255.0 / 255.0);
-
-pub const BLOCK_WIDTH: f32 = 25.0;
-pub const BLOCK_HEIGHT: f32 = 25.0;
+use crate::pieces::PieceSet;
+use crate::random::Random;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+//A position, on the grid or relative to a tetromino, counted in blocks.
 #[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point2<N> {
+    pub x: N,
+    pub y: N,
+}
+
+impl<N> Point2<N> {
+    pub const fn new(x: N, y: N) -> Point2<N> {
+        Point2 { x, y }
+    }
+}
+
+//A color with its red, green, blue and alpha going from 0.0 to 1.0, as the window draws them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum BlockState {
     Empty,
     Filled,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Block {
+    #[serde(with = "point_serde")]
     pub position: Point2<f32>,
+    #[serde(with = "color_serde")]
     pub color: Color,
     pub state: BlockState,
+    //Frame the block was locked on the grid.
+    pub locked_at: u32,
+    //Index of the piece the block belongs to, for themes to color it by.
+    #[serde(default)]
+    pub piece: Option<usize>,
 }
 
 impl Block {
@@ -40,11 +60,13 @@ impl Block {
             position: position,
             color: color,
             state: state,
+            locked_at: 0,
+            piece: None,
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Rotation {
     _0,
     _90,
@@ -53,6 +75,16 @@ pub enum Rotation {
 }
 
 impl Rotation {
+    //Position of the rotation state in per rotation tables.
+    pub fn index(&self) -> usize {
+        match self {
+            Rotation::_0 => 0,
+            Rotation::_90 => 1,
+            Rotation::_180 => 2,
+            Rotation::_270 => 3,
+        }
+    }
+
     pub fn rotate_cw(&mut self) -> &Rotation {
         match self {
             Rotation::_0 => *self = Rotation::_90,
@@ -62,7 +94,6 @@ impl Rotation {
         }
         self
     }
-    #[allow(dead_code)]
     pub fn rotate_ccw(&mut self) -> &Rotation {
         match self {
             Rotation::_0 => *self = Rotation::_270,
@@ -74,239 +105,64 @@ impl Rotation {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum TetrominoType {
-    I,
-    O,
-    T,
-    S,
-    Z,
-    J,
-    L,
-}
-
-impl Distribution<TetrominoType> for Standard {
-    fn sample<R>(&self, rng: &mut R) -> TetrominoType
-    where
-        R: rand::Rng + ?std::marker::Sized,
-    {
-        match rng.gen_range(0, 7) {
-            0 => I,
-            1 => O,
-            2 => T,
-            3 => S,
-            4 => Z,
-            5 => J,
-            6 | _ => L,
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tetromino {
+    #[serde(with = "point_serde")]
     pub position: Point2<f32>,
     pub rotation: Rotation,
-    pub t_type: TetrominoType,
+    //Index of the piece in the piece set the game is played with.
+    pub piece: usize,
+    //Blocks each cell of the piece takes up in either direction. This is 2 in big mode.
+    pub scale: u32,
 }
 
 impl Tetromino {
-    pub fn random(position: Point2<f32>, rotation: Rotation) -> Tetromino {
+    //Creates a tetromino of the given piece at its spawn position.
+    pub fn spawn(piece: usize, position: Point2<f32>, scale: u32, pieces: &PieceSet) -> Tetromino {
+        let (offset_x, offset_y) = pieces.pieces[piece].spawn_offset;
         Tetromino {
-            position: position,
-            rotation: rotation,
-            t_type: rand::random(),
+            position: Point2::new(
+                position.x + (offset_x * scale as i32) as f32,
+                position.y + (offset_y * scale as i32) as f32,
+            ),
+            rotation: Rotation::_0,
+            piece: piece,
+            scale: scale,
         }
     }
 
-    pub fn blocks(&self) -> Vec<Block> {
-        match self.t_type {
-            TetrominoType::I => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(-2.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_I, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 2.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_I, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(-2.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_I, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 2.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 0.0), COLOR_I, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_I, BlockState::Filled),
-                ],
-            },
-            TetrominoType::O => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_O, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_O, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_O, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_O, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_O, BlockState::Filled),
-                ],
-            },
-            TetrominoType::T => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_T, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_T, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_T, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_T, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_T, BlockState::Filled),
-                ],
-            },
-            TetrominoType::S => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_S, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 1.0), COLOR_S, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_S, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_S, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 1.0), COLOR_S, BlockState::Filled),
-                ],
-            },
-            TetrominoType::Z => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(1.0, -1.0), COLOR_Z, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_Z, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(1.0, -1.0), COLOR_Z, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_Z, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_Z, BlockState::Filled),
-                ],
-            },
-            TetrominoType::J => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_J, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_J, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(1.0, -1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_J, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_J, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_J, BlockState::Filled),
-                ],
-            },
-            TetrominoType::L => match self.rotation {
-                Rotation::_0 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, -1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_L, BlockState::Filled),
-                ],
-                Rotation::_90 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_L, BlockState::Filled),
-                ],
-                Rotation::_180 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(1.0, 1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(-1.0, 0.0), COLOR_L, BlockState::Filled),
-                ],
-                Rotation::_270 => vec![
-                    Block::new(Point2::new(0.0, 0.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(0.0, -1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(1.0, -1.0), COLOR_L, BlockState::Filled),
-                    Block::new(Point2::new(0.0, 1.0), COLOR_L, BlockState::Filled),
-                ],
-            },
-        }
+    pub fn random(
+        position: Point2<f32>,
+        scale: u32,
+        pieces: &PieceSet,
+        random: &mut Random,
+    ) -> Tetromino {
+        Tetromino::spawn(random.below(pieces.len()), position, scale, pieces)
+    }
+
+    //The blocks of the tetromino relative to its position. Every cell of the piece turns into a
+    //scale x scale square of blocks.
+    pub fn blocks(&self, pieces: &PieceSet) -> Vec<Block> {
+        let piece = &pieces.pieces[self.piece];
+        let scale = self.scale as i32;
+        piece.rotations[self.rotation.index()]
+            .iter()
+            .flat_map(|(x, y)| {
+                (0..scale * scale).map(move |i| (x * scale + i % scale, y * scale + i / scale))
+            })
+            .map(|(x, y)| Block {
+                piece: Some(self.piece),
+                ..Block::new(
+                    Point2::new(x as f32, y as f32),
+                    piece.color,
+                    BlockState::Filled,
+                )
+            })
+            .collect()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Grid {
     pub width: u32,
     pub height: u32,
@@ -319,14 +175,10 @@ impl Grid {
             width: width,
             height: height,
             blocks: (0..width)
-                .into_iter()
                 .map(|x| {
                     (0..height)
-                        .into_iter()
-                        .map(|y| Block {
-                            position: Point2::new(x as f32, y as f32),
-                            color: color,
-                            state: BlockState::Empty,
+                        .map(|y| {
+                            Block::new(Point2::new(x as f32, y as f32), color, BlockState::Empty)
                         })
                         .collect::<Vec<Block>>()
                 })
@@ -334,7 +186,7 @@ impl Grid {
         }
     }
 
-    pub fn find_line_clears(&mut self) -> Vec<u32> {
+    pub fn find_line_clears(&self) -> Vec<u32> {
         let mut empty_found: bool = false;
         let mut lines: Vec<u32> = Vec::new();
 
@@ -390,15 +242,51 @@ impl Grid {
         LineClears::get_clear_type(lines.len() as u32)
     }
 
-    pub fn check_occupied(&self, x: u32, y: u32) -> bool {
-        if x < self.width && y < self.height {
-            if self.blocks[x as usize][y as usize].state == BlockState::Empty {
-                return false;
+    //Pushes every block up a row and fills the bottom row with garbage, but for the columns of
+    //`hole`. Returns false if a block was pushed out the top of the grid.
+    pub fn raise(&mut self, hole: std::ops::Range<u32>, frame: u32) -> bool {
+        let mut fits = true;
+        for (x, row) in self.blocks.iter_mut().enumerate() {
+            if row.remove(0).state == BlockState::Filled {
+                fits = false;
             }
+            let state = if hole.contains(&(x as u32)) {
+                BlockState::Empty
+            } else {
+                BlockState::Filled
+            };
+            let mut block =
+                Block::new(Point2::new(0.0, 0.0), Color::new(0.5, 0.5, 0.5, 1.0), state);
+            block.locked_at = frame;
+            row.push(block);
+        }
+
+        for (x, row) in self.blocks.iter_mut().enumerate() {
+            for (y, col) in row.iter_mut().enumerate() {
+                col.position.x = x as f32;
+                col.position.y = y as f32;
+            }
+        }
+        fits
+    }
+
+    pub fn check_occupied(&self, x: u32, y: u32) -> bool {
+        if x < self.width
+            && y < self.height
+            && self.blocks[x as usize][y as usize].state == BlockState::Empty
+        {
+            return false;
         }
         true
     }
 
+    pub fn filled_blocks(&self) -> u32 {
+        self.blocks
+            .iter()
+            .map(|x| x.iter().filter(|y| y.state == BlockState::Filled).count() as u32)
+            .sum()
+    }
+
     pub fn reset(&mut self) {
         self.blocks
             .iter_mut()
@@ -406,23 +294,56 @@ impl Grid {
     }
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum LineClears {
-    NoClear = 0,
-    Single = 1,
-    Double = 2,
-    Tripple = 3,
-    Rustris = 4,
+    NoClear,
+    Single,
+    Double,
+    Tripple,
+    Rustris,
+    //Five lines or more, which pieces taller than a tetromino can clear.
+    More(u32),
 }
 
 impl LineClears {
-    fn get_clear_type(clear: u32) -> LineClears {
+    pub fn get_clear_type(clear: u32) -> LineClears {
         match clear {
             1 => LineClears::Single,
             2 => LineClears::Double,
             3 => LineClears::Tripple,
             4 => LineClears::Rustris,
-            _ => LineClears::NoClear,
+            0 => LineClears::NoClear,
+            _ => LineClears::More(clear),
         }
     }
 }
+
+//Saves points as (x, y) pairs.
+mod point_serde {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(point: &Point2<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        (point.x, point.y).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Point2<f32>, D::Error> {
+        let (x, y) = <(f32, f32)>::deserialize(deserializer)?;
+        Ok(Point2::new(x, y))
+    }
+}
+
+//Saves colors as (r, g, b, a) tuples.
+mod color_serde {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        (color.r, color.g, color.b, color.a).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let (r, g, b, a) = <(f32, f32, f32, f32)>::deserialize(deserializer)?;
+        Ok(Color::new(r, g, b, a))
+    }
+}